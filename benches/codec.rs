@@ -0,0 +1,101 @@
+//! Encode and decode cost of each cache codec on the `/v1/users` payload, and
+//! what a hit on the typed in-memory tier costs in comparison.
+//!
+//! Run with `cargo bench --bench codec`.
+
+// Only the codec and the model are compiled into the benchmark
+#![allow(dead_code)]
+
+use std::{any::Any, sync::Arc};
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+
+// Their unit tests are left out under the benchmark harness, their imports are not
+#[path = "../src/util/cache_key.rs"]
+#[allow(unused_imports)]
+mod cache_key;
+#[path = "../src/util/codec.rs"]
+#[allow(unused_imports)]
+mod codec;
+#[path = "../src/model/user.rs"]
+mod user;
+
+mod util {
+    pub(crate) use crate::cache_key;
+}
+
+use codec::CodecKind;
+use user::{Address, Company, Geo, User};
+
+const CODECS: [CodecKind; 3] = [CodecKind::Json, CodecKind::MessagePack, CodecKind::Bincode];
+
+/// Ten users shaped like the JSONPlaceholder response
+fn users() -> Vec<User> {
+    (1..=10)
+        .map(|id| User {
+            id,
+            name: format!("User Number {}", id),
+            username: format!("user{}", id),
+            email: format!("user{}@example.com", id),
+            address: Address {
+                street: format!("{} Main Street", id),
+                suite: format!("Apt. {}", id * 100),
+                city: "Gwenborough".to_string(),
+                zipcode: "92998-3874".to_string(),
+                geo: Geo {
+                    lat: "-37.3159".to_string(),
+                    lng: "81.1496".to_string(),
+                },
+            },
+            phone: "1-770-736-8031 x56442".to_string(),
+            website: "example.org".to_string(),
+            company: Company {
+                name: "Romaguera-Crona".to_string(),
+                catch_phrase: "Multi-layered client-server neural-net".to_string(),
+                bs: "harness real-time e-markets".to_string(),
+            },
+        })
+        .collect()
+}
+
+fn bench_codecs(c: &mut Criterion) {
+    let users = users();
+
+    let mut encode = c.benchmark_group("encode_users");
+    for kind in CODECS {
+        let size = kind.encode(&users).unwrap().len();
+        encode.bench_with_input(BenchmarkId::new(format!("{:?}", kind), size), &kind, |b, kind| {
+            b.iter(|| kind.encode(black_box(&users)).unwrap())
+        });
+    }
+    encode.finish();
+
+    let mut decode = c.benchmark_group("decode_users");
+    for kind in CODECS {
+        let encoded = kind.encode(&users).unwrap();
+        decode.bench_with_input(BenchmarkId::new(format!("{:?}", kind), encoded.len()), &encoded, |b, encoded| {
+            b.iter(|| codec::decode::<Vec<User>>(black_box(encoded)).unwrap())
+        });
+    }
+    decode.finish();
+}
+
+/// A memory hit before and after the typed tier: decoding the stored JSON
+/// versus taking the value out of a type-erased `Arc`
+fn bench_memory_hit(c: &mut Criterion) {
+    let users = users();
+    let encoded = CodecKind::Json.encode(&users).unwrap();
+    let erased: Arc<dyn Any + Send + Sync> = Arc::new(users);
+
+    let mut group = c.benchmark_group("memory_hit_users");
+    group.bench_function("json_decode", |b| {
+        b.iter(|| codec::decode::<Vec<User>>(black_box(&encoded)).unwrap())
+    });
+    group.bench_function("arc_downcast", |b| {
+        b.iter(|| black_box(&erased).clone().downcast::<Vec<User>>().unwrap())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_codecs, bench_memory_hit);
+criterion_main!(benches);
@@ -0,0 +1,18 @@
+use std::process::Command;
+
+fn main() {
+    // Prefer an explicit GIT_SHA (e.g. from CI), fall back to asking git
+    let git_sha = std::env::var("GIT_SHA").ok().or_else(|| {
+        Command::new("git")
+            .args(["rev-parse", "--short", "HEAD"])
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .and_then(|output| String::from_utf8(output.stdout).ok())
+            .map(|sha| sha.trim().to_string())
+    });
+
+    println!("cargo:rustc-env=GIT_SHA={}", git_sha.unwrap_or_else(|| "unknown".to_string()));
+    println!("cargo:rerun-if-env-changed=GIT_SHA");
+    println!("cargo:rerun-if-changed=.git/HEAD");
+}
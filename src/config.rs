@@ -0,0 +1,1094 @@
+use std::{
+    collections::HashMap,
+    env,
+    fmt,
+    net::ToSocketAddrs,
+};
+
+use axum::http::{HeaderName, HeaderValue};
+use config::{Config, Environment, File};
+use redis::IntoConnectionInfo;
+use sentry::IntoDsn;
+use serde::{de, Deserialize, Deserializer};
+
+use crate::{
+    model::{UserKey, UsersKey},
+    util::{cache_key::CacheKey, codec::CodecKind},
+};
+
+/// Path of the config file, without extension (`config.toml`, `config.yaml`, ...)
+const DEFAULT_CONFIG_FILE: &str = "config";
+
+/// Environment variable pointing at an alternative config file
+const CONFIG_FILE_ENV: &str = "APP_CONFIG";
+
+/// Prefix for structured environment overrides, e.g. `APP__REDIS__POOL_MAX_SIZE=50`
+const ENV_PREFIX: &str = "APP";
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Load(config::ConfigError), // Error reading or deserializing config sources
+    Invalid(Vec<String>),      // One or more values failed validation
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Load(error) => write!(f, "failed to load configuration: {}", error),
+            ConfigError::Invalid(problems) => {
+                writeln!(f, "invalid configuration:")?;
+                for problem in problems {
+                    writeln!(f, "  - {}", problem)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl From<config::ConfigError> for ConfigError {
+    fn from(error: config::ConfigError) -> Self {
+        ConfigError::Load(error)
+    }
+}
+
+/// Application configuration, see `config.example.toml` for every knob
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AppConfig {
+    pub server: ServerConfig,
+    pub sentry: SentryConfig,
+    pub cors: CorsConfig,
+    pub redis: RedisConfig,
+    pub cache: CacheConfig,
+    pub http_client: HttpClientConfig,
+    pub health: HealthConfig,
+    pub signing: SigningConfig,
+    pub rate_limit: RateLimitConfig,
+    pub admin: AdminConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    /// Address the HTTP listener binds to, `ip:port` or `host:port`
+    pub bind: String,
+    /// Maximum number of requests processed concurrently
+    pub concurrency_limit: usize,
+    /// Seconds to keep accepting connections while reporting not ready after SIGTERM/SIGINT
+    pub shutdown_delay_secs: u64,
+    /// Seconds to wait for in-flight requests to finish before they are aborted, then for background tasks
+    pub shutdown_timeout_secs: u64,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            bind: "0.0.0.0:8000".to_string(),
+            concurrency_limit: 1000,
+            shutdown_delay_secs: 0,
+            shutdown_timeout_secs: 30,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SentryConfig {
+    /// Sentry DSN, empty disables reporting
+    pub dsn: String,
+    /// Fraction of transactions sent to Sentry, 0.0..=1.0
+    pub traces_sample_rate: f32,
+}
+
+impl Default for SentryConfig {
+    fn default() -> Self {
+        Self {
+            dsn: String::new(),
+            traces_sample_rate: 0.2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CorsConfig {
+    /// Origin allowed to make credentialed cross-origin requests
+    pub allow_origin: String,
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        Self {
+            allow_origin: "http://localhost:3000".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RedisConfig {
+    /// Redis connection URL; with Sentinel, its credentials and database are used for the primary
+    pub url: String,
+    /// Deployment: `standalone`, `sentinel` or `cluster`
+    pub topology: RedisTopology,
+    /// Sentinel or cluster node URLs, unused in standalone mode
+    pub nodes: Vec<String>,
+    /// Name of the primary monitored by Sentinel
+    pub sentinel_master: String,
+    /// Optional pool of read replicas for cache reads
+    pub replicas: RedisReplicasConfig,
+    /// Maximum number of pooled connections; in a cluster they share one connection per node
+    pub pool_max_size: u32,
+    /// Minimum number of idle connections kept open
+    pub pool_min_idle: u32,
+    /// How long to wait for a pooled connection, in milliseconds
+    pub connection_timeout_ms: u64,
+    /// Idle connections are closed after this many seconds
+    pub idle_timeout_secs: u64,
+    /// How often to probe Redis while running in degraded moka-only mode, in seconds
+    pub reconnect_interval_secs: u64,
+}
+
+impl Default for RedisConfig {
+    fn default() -> Self {
+        Self {
+            url: "redis://localhost".to_string(),
+            topology: RedisTopology::Standalone,
+            nodes: Vec::new(),
+            sentinel_master: "mymaster".to_string(),
+            replicas: RedisReplicasConfig::default(),
+            pool_max_size: (num_cpus::get() * 10) as u32,
+            pool_min_idle: (num_cpus::get() * 2 + 1) as u32,
+            connection_timeout_ms: 2000,
+            idle_timeout_secs: 60,
+            reconnect_interval_secs: 5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RedisReplicasConfig {
+    /// Send cache reads to replicas, falling back to the primary while they are unavailable
+    pub enabled: bool,
+    /// Replica URLs, connections are spread over them; resolved through Sentinel when empty
+    pub urls: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RedisTopology {
+    Standalone,
+    Sentinel,
+    Cluster,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CacheConfig {
+    /// Time-to-live of the in-memory (moka) tier, never longer than the Redis entry, in seconds
+    pub memory_ttl_secs: u64,
+    /// Maximum number of entries in the in-memory tier
+    pub memory_capacity: u64,
+    /// Time-to-live of the Redis tier, in seconds
+    pub redis_ttl_secs: u64,
+    /// How long past their TTL values are still served while refreshed in the background, in seconds
+    pub stale_ttl_secs: u64,
+    /// Time-to-live of cached "not found" results, in seconds
+    pub negative_ttl_secs: u64,
+    /// Random fraction (0.0 to 1.0) added to every TTL so keys written together don't expire together
+    pub ttl_jitter: f64,
+    /// Serialization format of cached values: `json`, `messagepack` or `bincode`
+    pub codec: CodecKind,
+    /// Per-wrapper overrides of the TTLs above, keyed by wrapper name (`users`, `user`)
+    pub policies: HashMap<String, CachePolicyConfig>,
+    /// Redis pub/sub channel used to evict in-memory entries on every instance
+    pub invalidation_channel: String,
+    /// Cross-instance lock so only one replica fetches a missing key
+    pub refresh_lock: RefreshLockConfig,
+    /// Compression of large values stored in Redis
+    pub compression: CompressionConfig,
+    /// Keys loaded into the cache at startup, before the instance reports ready
+    pub warmup: WarmupConfig,
+    /// Keys refreshed from upstream before they expire
+    pub hot_keys: HotKeysConfig,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            memory_ttl_secs: 10,
+            memory_capacity: 16_000,
+            redis_ttl_secs: 10,
+            stale_ttl_secs: 10,
+            negative_ttl_secs: 10,
+            ttl_jitter: 0.1,
+            codec: CodecKind::Json,
+            policies: HashMap::new(),
+            invalidation_channel: "cache:invalidate".to_string(),
+            refresh_lock: RefreshLockConfig::default(),
+            compression: CompressionConfig::default(),
+            warmup: WarmupConfig::default(),
+            hot_keys: HotKeysConfig::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CachePolicyConfig {
+    pub memory_ttl_secs: Option<u64>,
+    pub redis_ttl_secs: Option<u64>,
+    pub stale_ttl_secs: Option<u64>,
+    pub negative_ttl_secs: Option<u64>,
+    pub ttl_jitter: Option<f64>,
+    pub codec: Option<CodecKind>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CompressionAlgorithm {
+    Zstd,
+    Gzip,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CompressionConfig {
+    /// Compress values written to Redis; compressed values are always readable
+    pub enabled: bool,
+    /// Algorithm used for new writes
+    pub algorithm: CompressionAlgorithm,
+    /// Values smaller than this are stored as plain JSON, in bytes
+    pub threshold_bytes: usize,
+    /// Compression level, 1-22 for zstd and 0-9 for gzip
+    pub level: u32,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            algorithm: CompressionAlgorithm::Zstd,
+            threshold_bytes: 1024,
+            level: 3,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RefreshLockConfig {
+    /// Take a Redis lock before fetching a key from upstream
+    pub enabled: bool,
+    /// How long the lock is held at most, should exceed the upstream timeout, in milliseconds
+    pub lock_ttl_ms: u64,
+    /// How long other replicas wait for the lock holder's value before fetching themselves, in milliseconds
+    pub wait_timeout_ms: u64,
+    /// How often waiting replicas poll Redis for the value, in milliseconds
+    pub poll_interval_ms: u64,
+}
+
+impl Default for RefreshLockConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            lock_ttl_ms: 5_000,
+            wait_timeout_ms: 2_000,
+            poll_interval_ms: 50,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WarmupConfig {
+    /// Warm the keys below at startup; readiness waits until they are loaded or the timeout passes
+    pub enabled: bool,
+    /// Keys as `{namespace}:{id}`, e.g. `users:all` or `user:1`
+    pub keys: Vec<String>,
+    /// How long readiness waits for the warm-up at most, in seconds
+    pub timeout_secs: u64,
+    /// Keys fetched at the same time
+    pub concurrency: usize,
+}
+
+impl Default for WarmupConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            keys: Vec::new(),
+            timeout_secs: 30,
+            concurrency: 4,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HotKeysConfig {
+    /// Refresh the keys below in the background before they turn stale
+    pub enabled: bool,
+    /// Keys as `{namespace}:{id}`, e.g. `users:all` or `user:1`
+    pub keys: Vec<String>,
+    /// How often the keys are checked, in seconds
+    pub interval_secs: u64,
+    /// A key is refetched once it turns stale within this many seconds
+    pub refresh_ahead_secs: u64,
+}
+
+impl Default for HotKeysConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            keys: Vec::new(),
+            interval_secs: 2,
+            refresh_ahead_secs: 5,
+        }
+    }
+}
+
+/// Cache entry that can be warmed or kept hot, written `{namespace}:{id}` in the config
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WarmKey {
+    Users,
+    User(i32),
+}
+
+impl WarmKey {
+    pub fn parse(key: &str) -> Option<Self> {
+        match key.split_once(':')? {
+            (UsersKey::NAMESPACE, "all") => Some(WarmKey::Users),
+            (UserKey::NAMESPACE, id) => id.parse().ok().map(WarmKey::User),
+            _ => None,
+        }
+    }
+
+    /// Namespace of the key, also the name of its cache policy
+    pub fn namespace(&self) -> &'static str {
+        match self {
+            WarmKey::Users => UsersKey::NAMESPACE,
+            WarmKey::User(_) => UserKey::NAMESPACE,
+        }
+    }
+}
+
+impl fmt::Display for WarmKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WarmKey::Users => write!(f, "{}:all", UsersKey::NAMESPACE),
+            WarmKey::User(id) => write!(f, "{}:{}", UserKey::NAMESPACE, id),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HttpClientConfig {
+    /// Total request timeout for upstream calls, retries included, in seconds
+    pub timeout_secs: u64,
+    /// Connect timeout for upstream calls, in seconds
+    pub connect_timeout_secs: u64,
+    /// Idle keep-alive connections kept per upstream host
+    pub pool_max_idle_per_host: usize,
+    /// Idle keep-alive connections are closed after this many seconds
+    pub pool_idle_timeout_secs: u64,
+    /// Per-host circuit breaker around cached upstream fetches
+    pub circuit_breaker: CircuitBreakerConfig,
+    /// Retries of idempotent upstream requests
+    pub retry: RetryConfig,
+}
+
+impl Default for HttpClientConfig {
+    fn default() -> Self {
+        Self {
+            timeout_secs: 30,
+            connect_timeout_secs: 10,
+            pool_max_idle_per_host: 10,
+            pool_idle_timeout_secs: 60,
+            circuit_breaker: CircuitBreakerConfig::default(),
+            retry: RetryConfig::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CircuitBreakerConfig {
+    /// Fail fast on upstream hosts that keep failing
+    pub enabled: bool,
+    /// Consecutive failures (connect errors, timeouts, 5xx) that open the circuit
+    pub failure_threshold: u32,
+    /// How long an open circuit rejects fetches before letting a trial through, in seconds
+    pub open_secs: u64,
+    /// Consecutive successful trials that close a half-open circuit
+    pub success_threshold: u32,
+    /// How long a copy of each value is kept to serve misses while the circuit is open, in seconds; 0 disables it
+    pub last_known_ttl_secs: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetryConfig {
+    /// Retry connect errors, timeouts, 5xx and 429 responses within `http_client.timeout_secs`
+    pub enabled: bool,
+    /// Attempts per request, the first one included
+    pub max_attempts: u32,
+    /// Backoff before the first retry, doubled for each one after, in milliseconds
+    pub base_delay_ms: u64,
+    /// Upper bound of the backoff, in milliseconds
+    pub max_delay_ms: u64,
+    /// Longest `Retry-After` that is waited for; a longer one ends the retries, in seconds
+    pub max_retry_after_secs: u64,
+    /// Retries earned by each request, e.g. 0.1 allows one retry per ten requests
+    pub budget_ratio: f64,
+    /// Retries that can be banked, and are available at startup
+    pub budget_burst: u32,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_attempts: 3,
+            base_delay_ms: 100,
+            max_delay_ms: 2000,
+            max_retry_after_secs: 5,
+            budget_ratio: 0.1,
+            budget_burst: 10,
+        }
+    }
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            failure_threshold: 5,
+            open_secs: 30,
+            success_threshold: 1,
+            last_known_ttl_secs: 86400,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HealthConfig {
+    /// Upstream URL probed with a HEAD request by the readiness check
+    pub upstream_url: String,
+    /// Timeout for each dependency check, in milliseconds
+    pub check_timeout_ms: u64,
+    /// Report not ready while Redis is unreachable instead of degraded
+    pub require_redis: bool,
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        Self {
+            upstream_url: "https://jsonplaceholder.typicode.com".to_string(),
+            check_timeout_ms: 2000,
+            require_redis: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SigningConfig {
+    /// Require HMAC-signed requests on protected routes, on by default in release builds
+    pub enabled: bool,
+    /// Maximum difference between `x-timestamp` and server time, in seconds
+    pub max_skew_secs: u64,
+    /// Largest request body that will be buffered and hashed, in bytes
+    pub max_body_bytes: usize,
+    /// Reject signed requests without an `x-nonce` header
+    pub require_nonce: bool,
+    /// Named HMAC-SHA256 secrets, selected by the `x-key-id` header (case-insensitive)
+    #[serde(deserialize_with = "lowercase_keys")]
+    pub keys: HashMap<String, String>,
+}
+
+/// Lowercases key ids, which keep their case when read from a file, so lookups by
+/// the lowercased `x-key-id` find them; ids differing only in case are rejected
+fn lowercase_keys<'de, D: Deserializer<'de>>(deserializer: D) -> Result<HashMap<String, String>, D::Error> {
+    let mut keys = HashMap::new();
+    for (key_id, secret) in HashMap::<String, String>::deserialize(deserializer)? {
+        let key_id = key_id.to_lowercase();
+        if keys.insert(key_id.clone(), secret).is_some() {
+            return Err(de::Error::custom(format!("signing key id '{}' is configured more than once", key_id)));
+        }
+    }
+    Ok(keys)
+}
+
+impl Default for SigningConfig {
+    fn default() -> Self {
+        Self {
+            enabled: !cfg!(debug_assertions),
+            max_skew_secs: 30,
+            max_body_bytes: 1024 * 1024,
+            require_nonce: false,
+            keys: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitConfig {
+    /// Apply per-client rate limits to protected routes
+    pub enabled: bool,
+    /// Default bucket size: requests allowed per window
+    pub requests: u32,
+    /// Default window over which the bucket refills, in seconds
+    pub window_secs: u64,
+    /// Header identifying API key clients, who are limited per key instead of per IP
+    pub api_key_header: String,
+    /// API keys limited per key; any other key sent in `api_key_header` is limited per IP
+    pub api_keys: Vec<String>,
+    /// Use the last `x-forwarded-for` address, the one the proxy appended, as the client IP (only behind a trusted proxy)
+    pub trust_forwarded_for: bool,
+    /// Let requests through while Redis is unavailable instead of answering 503
+    pub fail_open: bool,
+    /// Per-route overrides, matched against the route template such as `/v1/user/{id}`
+    pub routes: Vec<RouteRateLimit>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RouteRateLimit {
+    pub route: String,
+    pub requests: u32,
+    pub window_secs: u64,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            requests: 100,
+            window_secs: 60,
+            api_key_header: "x-api-key".to_string(),
+            api_keys: Vec::new(),
+            trust_forwarded_for: false,
+            fail_open: true,
+            routes: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AdminConfig {
+    /// Bearer tokens accepted on `/v1/admin` routes; the admin API is refused while empty
+    pub tokens: Vec<String>,
+}
+
+impl AppConfig {
+    /// Loads the configuration from the config file and environment, then validates it.
+    ///
+    /// Sources, lowest to highest priority:
+    /// 1. built-in defaults
+    /// 2. `config.{toml,yaml}` in the working directory, or the file named by `APP_CONFIG`
+    /// 3. `APP__SECTION__KEY` environment variables
+    /// 4. legacy variables `SERVER_BIND`, `SENTRY_DSN`, `CORS_HOST`, `REDIS_URL`
+    pub fn load() -> Result<Self, ConfigError> {
+        let config_file = env::var(CONFIG_FILE_ENV).ok();
+        let file = match &config_file {
+            Some(path) => File::with_name(path).required(true),
+            None => File::with_name(DEFAULT_CONFIG_FILE).required(false),
+        };
+
+        let config: AppConfig = Config::builder()
+            .add_source(file)
+            .add_source(
+                Environment::with_prefix(ENV_PREFIX)
+                    .separator("__")
+                    .try_parsing(true)
+                    // Comma-separated, e.g. `APP__REDIS__NODES=redis://a:6379,redis://b:6379`
+                    .list_separator(",")
+                    .with_list_parse_key("redis.nodes")
+                    .with_list_parse_key("redis.replicas.urls")
+                    .with_list_parse_key("rate_limit.api_keys")
+                    .with_list_parse_key("admin.tokens"),
+            )
+            .set_override_option("server.bind", env::var("SERVER_BIND").ok())?
+            .set_override_option("sentry.dsn", env::var("SENTRY_DSN").ok())?
+            .set_override_option("cors.allow_origin", env::var("CORS_HOST").ok())?
+            .set_override_option("redis.url", env::var("REDIS_URL").ok())?
+            .build()?
+            .try_deserialize()?;
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Checks every value up front and reports all problems at once
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let mut problems = Vec::new();
+
+        // `host:port` is accepted like `TcpListener::bind` does, resolving the host
+        if self.server.bind.to_socket_addrs().map_or(true, |mut addrs| addrs.next().is_none()) {
+            problems.push(format!("server.bind: '{}' is not a valid `host:port` address", self.server.bind));
+        }
+        if self.server.concurrency_limit == 0 {
+            problems.push("server.concurrency_limit: must be greater than 0".to_string());
+        }
+        if self.server.shutdown_timeout_secs == 0 {
+            problems.push("server.shutdown_timeout_secs: must be greater than 0".to_string());
+        }
+
+        if let Err(error) = self.sentry.dsn.as_str().into_dsn() {
+            problems.push(format!("sentry.dsn: {}", error));
+        }
+        if !(0.0..=1.0).contains(&self.sentry.traces_sample_rate) {
+            problems.push(format!(
+                "sentry.traces_sample_rate: {} is outside 0.0..=1.0",
+                self.sentry.traces_sample_rate
+            ));
+        }
+
+        let origin = &self.cors.allow_origin;
+        if !(origin.starts_with("http://") || origin.starts_with("https://"))
+            || origin.parse::<HeaderValue>().is_err()
+        {
+            problems.push(format!("cors.allow_origin: '{}' is not a valid http(s) origin", origin));
+        }
+
+        if let Err(error) = self.redis.url.as_str().into_connection_info() {
+            problems.push(format!("redis.url: {}", error));
+        }
+        if self.redis.topology != RedisTopology::Standalone {
+            if self.redis.nodes.is_empty() {
+                problems.push(format!("redis.nodes: must not be empty with the {:?} topology", self.redis.topology));
+            }
+            for node in &self.redis.nodes {
+                if let Err(error) = node.as_str().into_connection_info() {
+                    problems.push(format!("redis.nodes: '{}': {}", node, error));
+                }
+            }
+        }
+        if self.redis.topology == RedisTopology::Sentinel && self.redis.sentinel_master.trim().is_empty() {
+            problems.push("redis.sentinel_master: must not be empty with the Sentinel topology".to_string());
+        }
+        let replicas = &self.redis.replicas;
+        if replicas.enabled {
+            match self.redis.topology {
+                RedisTopology::Cluster => {
+                    problems.push("redis.replicas.enabled: not supported with the Cluster topology".to_string());
+                }
+                RedisTopology::Standalone if replicas.urls.is_empty() => {
+                    problems.push("redis.replicas.urls: must not be empty with the Standalone topology".to_string());
+                }
+                _ => {}
+            }
+            for url in &replicas.urls {
+                if let Err(error) = url.as_str().into_connection_info() {
+                    problems.push(format!("redis.replicas.urls: '{}': {}", url, error));
+                }
+            }
+        }
+        if self.redis.pool_max_size == 0 {
+            problems.push("redis.pool_max_size: must be greater than 0".to_string());
+        }
+        if self.redis.pool_min_idle > self.redis.pool_max_size {
+            problems.push(format!(
+                "redis.pool_min_idle: {} exceeds redis.pool_max_size {}",
+                self.redis.pool_min_idle, self.redis.pool_max_size
+            ));
+        }
+        if self.redis.connection_timeout_ms == 0 {
+            problems.push("redis.connection_timeout_ms: must be greater than 0".to_string());
+        }
+        if self.redis.reconnect_interval_secs == 0 {
+            problems.push("redis.reconnect_interval_secs: must be greater than 0".to_string());
+        }
+
+        if self.cache.memory_ttl_secs == 0 {
+            problems.push("cache.memory_ttl_secs: must be greater than 0".to_string());
+        }
+        if self.cache.memory_capacity == 0 {
+            problems.push("cache.memory_capacity: must be greater than 0".to_string());
+        }
+        if self.cache.redis_ttl_secs == 0 {
+            problems.push("cache.redis_ttl_secs: must be greater than 0".to_string());
+        }
+        if self.cache.negative_ttl_secs == 0 {
+            problems.push("cache.negative_ttl_secs: must be greater than 0".to_string());
+        }
+        if !(0.0..=1.0).contains(&self.cache.ttl_jitter) {
+            problems.push("cache.ttl_jitter: must be between 0.0 and 1.0".to_string());
+        }
+        for (name, policy) in &self.cache.policies {
+            let ttls = [
+                ("memory_ttl_secs", policy.memory_ttl_secs),
+                ("redis_ttl_secs", policy.redis_ttl_secs),
+                ("negative_ttl_secs", policy.negative_ttl_secs),
+            ];
+            for (field, ttl) in ttls {
+                if ttl == Some(0) {
+                    problems.push(format!("cache.policies.{}.{}: must be greater than 0", name, field));
+                }
+            }
+            if policy.ttl_jitter.is_some_and(|jitter| !(0.0..=1.0).contains(&jitter)) {
+                problems.push(format!("cache.policies.{}.ttl_jitter: must be between 0.0 and 1.0", name));
+            }
+        }
+        if self.cache.invalidation_channel.trim().is_empty() {
+            problems.push("cache.invalidation_channel: must not be empty".to_string());
+        }
+        let compression = &self.cache.compression;
+        let max_level = match compression.algorithm {
+            CompressionAlgorithm::Zstd => 22,
+            CompressionAlgorithm::Gzip => 9,
+        };
+        if compression.level > max_level {
+            problems.push(format!("cache.compression.level: must be at most {} for {:?}", max_level, compression.algorithm));
+        }
+        let lock = &self.cache.refresh_lock;
+        if lock.lock_ttl_ms == 0 {
+            problems.push("cache.refresh_lock.lock_ttl_ms: must be greater than 0".to_string());
+        }
+        if lock.poll_interval_ms == 0 {
+            problems.push("cache.refresh_lock.poll_interval_ms: must be greater than 0".to_string());
+        }
+        if lock.wait_timeout_ms < lock.poll_interval_ms {
+            problems.push("cache.refresh_lock.wait_timeout_ms: must be at least poll_interval_ms".to_string());
+        }
+        let warm_keys = [("cache.warmup.keys", &self.cache.warmup.keys), ("cache.hot_keys.keys", &self.cache.hot_keys.keys)];
+        for (field, keys) in warm_keys {
+            for key in keys.iter().filter(|key| WarmKey::parse(key).is_none()) {
+                problems.push(format!("{}: '{}' is not a known key, expected e.g. 'users:all' or 'user:1'", field, key));
+            }
+        }
+        if self.cache.warmup.timeout_secs == 0 {
+            problems.push("cache.warmup.timeout_secs: must be greater than 0".to_string());
+        }
+        if self.cache.warmup.concurrency == 0 {
+            problems.push("cache.warmup.concurrency: must be greater than 0".to_string());
+        }
+        let hot_keys = &self.cache.hot_keys;
+        if hot_keys.interval_secs == 0 {
+            problems.push("cache.hot_keys.interval_secs: must be greater than 0".to_string());
+        }
+        if hot_keys.enabled {
+            if hot_keys.refresh_ahead_secs <= hot_keys.interval_secs {
+                problems.push("cache.hot_keys.refresh_ahead_secs: must be greater than interval_secs, or keys can turn stale between checks".to_string());
+            }
+            for key in hot_keys.keys.iter().filter_map(|key| WarmKey::parse(key)) {
+                let redis_ttl_secs = self
+                    .cache
+                    .policies
+                    .get(key.namespace())
+                    .and_then(|policy| policy.redis_ttl_secs)
+                    .unwrap_or(self.cache.redis_ttl_secs);
+                if hot_keys.refresh_ahead_secs >= redis_ttl_secs {
+                    problems.push(format!(
+                        "cache.hot_keys.refresh_ahead_secs: must be less than the {}s Redis TTL of '{}', or it is refetched on every check",
+                        redis_ttl_secs, key
+                    ));
+                }
+            }
+        }
+
+        if self.http_client.timeout_secs == 0 {
+            problems.push("http_client.timeout_secs: must be greater than 0".to_string());
+        }
+        if self.http_client.connect_timeout_secs == 0 {
+            problems.push("http_client.connect_timeout_secs: must be greater than 0".to_string());
+        }
+        let breaker = &self.http_client.circuit_breaker;
+        if breaker.failure_threshold == 0 {
+            problems.push("http_client.circuit_breaker.failure_threshold: must be greater than 0".to_string());
+        }
+        if breaker.open_secs == 0 {
+            problems.push("http_client.circuit_breaker.open_secs: must be greater than 0".to_string());
+        }
+        if breaker.success_threshold == 0 {
+            problems.push("http_client.circuit_breaker.success_threshold: must be greater than 0".to_string());
+        }
+        let retry = &self.http_client.retry;
+        if retry.max_attempts == 0 {
+            problems.push("http_client.retry.max_attempts: must be greater than 0".to_string());
+        }
+        if retry.base_delay_ms == 0 {
+            problems.push("http_client.retry.base_delay_ms: must be greater than 0".to_string());
+        }
+        if retry.max_delay_ms < retry.base_delay_ms {
+            problems.push("http_client.retry.max_delay_ms: must be at least base_delay_ms".to_string());
+        }
+        if !(0.0..=1.0).contains(&retry.budget_ratio) {
+            problems.push("http_client.retry.budget_ratio: must be between 0.0 and 1.0".to_string());
+        }
+
+        if reqwest::Url::parse(&self.health.upstream_url).is_err() {
+            problems.push(format!("health.upstream_url: '{}' is not a valid URL", self.health.upstream_url));
+        }
+        if self.health.check_timeout_ms == 0 {
+            problems.push("health.check_timeout_ms: must be greater than 0".to_string());
+        }
+
+        if self.signing.max_skew_secs == 0 {
+            problems.push("signing.max_skew_secs: must be greater than 0".to_string());
+        }
+        for (key_id, secret) in &self.signing.keys {
+            if secret.len() < 32 {
+                problems.push(format!("signing.keys.{}: secret must be at least 32 bytes", key_id));
+            }
+        }
+
+        let rate_limits = std::iter::once(("rate_limit".to_string(), self.rate_limit.requests, self.rate_limit.window_secs))
+            .chain(self.rate_limit.routes.iter().map(|r| {
+                (format!("rate_limit.routes[{}]", r.route), r.requests, r.window_secs)
+            }));
+        for (name, requests, window_secs) in rate_limits {
+            if requests == 0 || window_secs == 0 {
+                problems.push(format!("{}: requests and window_secs must be greater than 0", name));
+            }
+        }
+        if HeaderName::from_bytes(self.rate_limit.api_key_header.as_bytes()).is_err() {
+            problems.push(format!(
+                "rate_limit.api_key_header: '{}' is not a valid header name",
+                self.rate_limit.api_key_header
+            ));
+        }
+        if self.rate_limit.api_keys.iter().any(|key| key.is_empty()) {
+            problems.push("rate_limit.api_keys: keys must not be empty".to_string());
+        }
+
+        if self.admin.tokens.iter().any(|token| token.len() < 32) {
+            problems.push("admin.tokens: tokens must be at least 32 bytes".to_string());
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError::Invalid(problems))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::PathBuf, sync::Mutex};
+
+    use super::*;
+
+    /// Serializes tests that read or change the process environment
+    static ENV: Mutex<()> = Mutex::new(());
+
+    /// Makes a default config invalid in one way
+    type BreakConfig = fn(&mut AppConfig);
+
+    /// Loads the config with `vars` set and `toml` as the config file, restoring the environment after
+    fn load_with(toml: &str, vars: &[(&str, &str)]) -> Result<AppConfig, ConfigError> {
+        let _env = ENV.lock().unwrap_or_else(|e| e.into_inner());
+        let path = std::env::temp_dir().join(format!("app-config-{}.toml", uuid::Uuid::new_v4()));
+        std::fs::write(&path, toml).unwrap();
+
+        env::set_var(CONFIG_FILE_ENV, &path);
+        for (name, value) in vars {
+            env::set_var(name, value);
+        }
+        let result = AppConfig::load();
+        for (name, _) in vars {
+            env::remove_var(name);
+        }
+        env::remove_var(CONFIG_FILE_ENV);
+        std::fs::remove_file(path).unwrap();
+        result
+    }
+
+    fn problems(config: &AppConfig) -> Vec<String> {
+        match config.validate() {
+            Ok(()) => Vec::new(),
+            Err(ConfigError::Invalid(problems)) => problems,
+            Err(e) => panic!("unexpected error: {}", e),
+        }
+    }
+
+    #[test]
+    fn defaults_are_valid() {
+        assert_eq!(problems(&AppConfig::default()), Vec::<String>::new());
+    }
+
+    #[test]
+    fn example_config_loads() {
+        let example = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("config.example.toml");
+        let config = load_with(&std::fs::read_to_string(example).unwrap(), &[]).unwrap();
+
+        assert_eq!(config.server.bind, ServerConfig::default().bind);
+        assert_eq!(config.cache.redis_ttl_secs, CacheConfig::default().redis_ttl_secs);
+    }
+
+    #[test]
+    fn environment_overrides_the_file() {
+        let config = load_with(
+            "[redis]\npool_max_size = 20\n[cache]\nredis_ttl_secs = 30\n",
+            &[
+                ("APP__REDIS__POOL_MAX_SIZE", "50"),
+                ("APP__REDIS__NODES", "redis://a:6379,redis://b:6379"),
+                ("REDIS_URL", "redis://legacy:6379"),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(config.redis.pool_max_size, 50);
+        assert_eq!(config.redis.nodes, ["redis://a:6379", "redis://b:6379"]);
+        assert_eq!(config.redis.url, "redis://legacy:6379");
+        assert_eq!(config.cache.redis_ttl_secs, 30);
+        assert_eq!(config.cache.memory_ttl_secs, CacheConfig::default().memory_ttl_secs);
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        let result = load_with("[cache]\nredis_tl_secs = 30\n", &[]);
+
+        assert!(matches!(result, Err(ConfigError::Load(e)) if e.to_string().contains("redis_tl_secs")));
+    }
+
+    #[test]
+    fn signing_key_ids_are_lowercased() {
+        let secret = "s".repeat(32);
+        let config = load_with(&format!("[signing.keys]\nPartner = \"{}\"\n", secret), &[]).unwrap();
+
+        assert_eq!(config.signing.keys.get("partner"), Some(&secret));
+    }
+
+    #[test]
+    fn every_invalid_value_is_reported() {
+        let cases: Vec<(&str, BreakConfig)> = vec![
+            ("server.bind", |c| c.server.bind = "nowhere".to_string()),
+            ("server.concurrency_limit", |c| c.server.concurrency_limit = 0),
+            ("server.shutdown_timeout_secs", |c| c.server.shutdown_timeout_secs = 0),
+            ("sentry.dsn", |c| c.sentry.dsn = "not a dsn".to_string()),
+            ("sentry.traces_sample_rate", |c| c.sentry.traces_sample_rate = 1.5),
+            ("cors.allow_origin", |c| c.cors.allow_origin = "ftp://example.com".to_string()),
+            ("redis.url", |c| c.redis.url = "not a url".to_string()),
+            ("redis.nodes", |c| c.redis.topology = RedisTopology::Cluster),
+            ("redis.nodes", |c| {
+                c.redis.topology = RedisTopology::Cluster;
+                c.redis.nodes = vec!["not a url".to_string()];
+            }),
+            ("redis.sentinel_master", |c| {
+                c.redis.topology = RedisTopology::Sentinel;
+                c.redis.nodes = vec!["redis://sentinel:26379".to_string()];
+                c.redis.sentinel_master = " ".to_string();
+            }),
+            ("redis.replicas.enabled", |c| {
+                c.redis.topology = RedisTopology::Cluster;
+                c.redis.nodes = vec!["redis://node:6379".to_string()];
+                c.redis.replicas.enabled = true;
+            }),
+            ("redis.replicas.urls", |c| c.redis.replicas.enabled = true),
+            ("redis.replicas.urls", |c| {
+                c.redis.replicas.enabled = true;
+                c.redis.replicas.urls = vec!["not a url".to_string()];
+            }),
+            ("redis.pool_max_size", |c| c.redis.pool_max_size = 0),
+            ("redis.pool_min_idle", |c| c.redis.pool_min_idle = c.redis.pool_max_size + 1),
+            ("redis.connection_timeout_ms", |c| c.redis.connection_timeout_ms = 0),
+            ("redis.reconnect_interval_secs", |c| c.redis.reconnect_interval_secs = 0),
+            ("cache.memory_ttl_secs", |c| c.cache.memory_ttl_secs = 0),
+            ("cache.memory_capacity", |c| c.cache.memory_capacity = 0),
+            ("cache.redis_ttl_secs", |c| c.cache.redis_ttl_secs = 0),
+            ("cache.negative_ttl_secs", |c| c.cache.negative_ttl_secs = 0),
+            ("cache.ttl_jitter", |c| c.cache.ttl_jitter = 1.5),
+            ("cache.policies.user.redis_ttl_secs", |c| {
+                let policy = CachePolicyConfig { redis_ttl_secs: Some(0), ..Default::default() };
+                c.cache.policies.insert("user".to_string(), policy);
+            }),
+            ("cache.policies.user.ttl_jitter", |c| {
+                let policy = CachePolicyConfig { ttl_jitter: Some(-0.5), ..Default::default() };
+                c.cache.policies.insert("user".to_string(), policy);
+            }),
+            ("cache.invalidation_channel", |c| c.cache.invalidation_channel = " ".to_string()),
+            ("cache.compression.level", |c| c.cache.compression.level = 23),
+            ("cache.compression.level", |c| {
+                c.cache.compression.algorithm = CompressionAlgorithm::Gzip;
+                c.cache.compression.level = 10;
+            }),
+            ("cache.refresh_lock.lock_ttl_ms", |c| c.cache.refresh_lock.lock_ttl_ms = 0),
+            ("cache.refresh_lock.poll_interval_ms", |c| c.cache.refresh_lock.poll_interval_ms = 0),
+            ("cache.refresh_lock.wait_timeout_ms", |c| {
+                c.cache.refresh_lock.wait_timeout_ms = c.cache.refresh_lock.poll_interval_ms - 1;
+            }),
+            ("cache.warmup.keys", |c| c.cache.warmup.keys = vec!["posts:1".to_string()]),
+            ("cache.hot_keys.keys", |c| c.cache.hot_keys.keys = vec!["user:one".to_string()]),
+            ("cache.warmup.timeout_secs", |c| c.cache.warmup.timeout_secs = 0),
+            ("cache.warmup.concurrency", |c| c.cache.warmup.concurrency = 0),
+            ("cache.hot_keys.interval_secs", |c| c.cache.hot_keys.interval_secs = 0),
+            ("cache.hot_keys.refresh_ahead_secs", |c| {
+                c.cache.hot_keys.enabled = true;
+                c.cache.hot_keys.refresh_ahead_secs = c.cache.hot_keys.interval_secs;
+            }),
+            ("cache.hot_keys.refresh_ahead_secs", |c| {
+                c.cache.hot_keys.enabled = true;
+                c.cache.hot_keys.keys = vec!["user:1".to_string()];
+                c.cache.hot_keys.refresh_ahead_secs = c.cache.redis_ttl_secs;
+            }),
+            ("cache.hot_keys.refresh_ahead_secs", |c| {
+                c.cache.hot_keys.enabled = true;
+                c.cache.hot_keys.keys = vec!["users:all".to_string()];
+                let policy = CachePolicyConfig { redis_ttl_secs: Some(4), ..Default::default() };
+                c.cache.policies.insert("users".to_string(), policy);
+            }),
+            ("http_client.timeout_secs", |c| c.http_client.timeout_secs = 0),
+            ("http_client.connect_timeout_secs", |c| c.http_client.connect_timeout_secs = 0),
+            ("http_client.circuit_breaker.failure_threshold", |c| c.http_client.circuit_breaker.failure_threshold = 0),
+            ("http_client.circuit_breaker.open_secs", |c| c.http_client.circuit_breaker.open_secs = 0),
+            ("http_client.circuit_breaker.success_threshold", |c| c.http_client.circuit_breaker.success_threshold = 0),
+            ("http_client.retry.max_attempts", |c| c.http_client.retry.max_attempts = 0),
+            ("http_client.retry.base_delay_ms", |c| c.http_client.retry.base_delay_ms = 0),
+            ("http_client.retry.max_delay_ms", |c| c.http_client.retry.max_delay_ms = c.http_client.retry.base_delay_ms - 1),
+            ("http_client.retry.budget_ratio", |c| c.http_client.retry.budget_ratio = 1.5),
+            ("health.upstream_url", |c| c.health.upstream_url = "not a url".to_string()),
+            ("health.check_timeout_ms", |c| c.health.check_timeout_ms = 0),
+            ("signing.max_skew_secs", |c| c.signing.max_skew_secs = 0),
+            ("signing.keys.partner", |c| {
+                c.signing.keys.insert("partner".to_string(), "short".to_string());
+            }),
+            ("rate_limit:", |c| c.rate_limit.requests = 0),
+            ("rate_limit.routes[/v1/users]", |c| {
+                c.rate_limit.routes.push(RouteRateLimit { route: "/v1/users".to_string(), requests: 10, window_secs: 0 });
+            }),
+            ("rate_limit.api_key_header", |c| c.rate_limit.api_key_header = "x api key".to_string()),
+            ("rate_limit.api_keys", |c| c.rate_limit.api_keys = vec![String::new()]),
+            ("admin.tokens", |c| c.admin.tokens = vec!["short".to_string()]),
+        ];
+
+        for (field, break_config) in cases {
+            let mut config = AppConfig::default();
+            break_config(&mut config);
+            let problems = problems(&config);
+            assert!(
+                problems.iter().any(|problem| problem.starts_with(field)),
+                "expected a problem with {}, got {:?}",
+                field,
+                problems
+            );
+        }
+    }
+
+    #[test]
+    fn hot_key_timing_is_only_checked_when_enabled() {
+        let mut config = AppConfig::default();
+        config.cache.hot_keys.keys = vec!["user:1".to_string()];
+        config.cache.hot_keys.refresh_ahead_secs = 60;
+
+        assert_eq!(problems(&config), Vec::<String>::new());
+    }
+
+    #[test]
+    fn all_problems_are_reported_at_once() {
+        let mut config = AppConfig::default();
+        config.server.concurrency_limit = 0;
+        config.cache.memory_capacity = 0;
+
+        assert_eq!(problems(&config).len(), 2);
+    }
+}
@@ -1,5 +1,5 @@
 use axum::{
-    http::StatusCode,
+    http::{header::RETRY_AFTER, HeaderValue, StatusCode},
     response::{IntoResponse, Response},
     extract::rejection::QueryRejection,
     Json,
@@ -22,9 +22,14 @@ pub enum ApiError {
     BadRequest,
     Unauthorized,
     Forbidden,
+    MissingSignature,
+    SignatureExpired,
+    InvalidSignature,
+    ReplayedRequest,
     NotFound(String),
     Conflict(String),
     Timeout,
+    RateLimited(u64),
     InternalServerError,
     Redis(RunError<RedisError>),
     Reqwest(ReqwestError),
@@ -38,9 +43,14 @@ impl ApiError {
             ApiError::BadRequest => StatusCode::BAD_REQUEST,
             ApiError::Unauthorized => StatusCode::UNAUTHORIZED,
             ApiError::Forbidden => StatusCode::FORBIDDEN,
+            ApiError::MissingSignature => StatusCode::UNAUTHORIZED,
+            ApiError::SignatureExpired => StatusCode::UNAUTHORIZED,
+            ApiError::InvalidSignature => StatusCode::UNAUTHORIZED,
+            ApiError::ReplayedRequest => StatusCode::CONFLICT,
             ApiError::NotFound(_) => StatusCode::NOT_FOUND,
             ApiError::Conflict(_) => StatusCode::CONFLICT,
             ApiError::Timeout => StatusCode::GATEWAY_TIMEOUT,
+            ApiError::RateLimited(_) => StatusCode::TOO_MANY_REQUESTS,
             ApiError::InternalServerError => StatusCode::INTERNAL_SERVER_ERROR,
             ApiError::Redis(_) => StatusCode::INTERNAL_SERVER_ERROR,
             ApiError::Reqwest(_) => StatusCode::INTERNAL_SERVER_ERROR,
@@ -54,9 +64,14 @@ impl ApiError {
             ApiError::BadRequest => "bad request".to_string(),
             ApiError::Unauthorized => "unauthorised".to_string(),
             ApiError::Forbidden => "forbidden".to_string(),
+            ApiError::MissingSignature => "missing request signature".to_string(),
+            ApiError::SignatureExpired => "request signature expired".to_string(),
+            ApiError::InvalidSignature => "invalid request signature".to_string(),
+            ApiError::ReplayedRequest => "request nonce has already been used".to_string(),
             ApiError::NotFound(error) => if error.is_empty() { "not found".to_string() } else { error.clone() },
             ApiError::Conflict(error) => if error.is_empty() { "conflict".to_string() } else { error.clone() },
             ApiError::Timeout => "request timed out".to_string(),
+            ApiError::RateLimited(_) => "too many requests".to_string(),
             ApiError::InternalServerError => "internal error".to_string(),
             ApiError::Redis(error) => format!("redis error: {}", error),
             ApiError::Reqwest(error) => format!("HTTP request error: {}", error),
@@ -101,22 +116,51 @@ impl From<PathRejection> for ApiError {
     }
 }
 
+/// Message of a 304 the upstream sent without being asked for a conditional response
+const UNEXPECTED_NOT_MODIFIED: &str = "upstream answered an unconditional request with 304";
+
 impl From<CacheError> for ApiError {
     fn from(err: CacheError) -> Self {
         match err {
             CacheError::Redis(e) => ApiError::Redis(e),
             CacheError::Reqwest(e) => ApiError::Reqwest(e),
             CacheError::Serialization(e) => ApiError::Serialization(e),
+            CacheError::Codec(e) => ApiError::Custom(StatusCode::INTERNAL_SERVER_ERROR, format!("cache codec error: {}", e)),
             CacheError::NotFound => ApiError::NotFound("Resource not found".to_string()),
+            CacheError::CircuitOpen => ApiError::Custom(StatusCode::SERVICE_UNAVAILABLE, "upstream unavailable".to_string()),
+            CacheError::UnexpectedNotModified => ApiError::Custom(StatusCode::BAD_GATEWAY, UNEXPECTED_NOT_MODIFIED.to_string()),
+            CacheError::Shared(e) => ApiError::from(&*e),
         }
     }
 }
 
+// Errors shared between coalesced requests can't be moved out, so they are rebuilt from a reference
+impl From<&CacheError> for ApiError {
+    fn from(err: &CacheError) -> Self {
+        let error = match err {
+            CacheError::NotFound => return ApiError::NotFound("Resource not found".to_string()),
+            CacheError::CircuitOpen => return ApiError::Custom(StatusCode::SERVICE_UNAVAILABLE, "upstream unavailable".to_string()),
+            CacheError::UnexpectedNotModified => return ApiError::Custom(StatusCode::BAD_GATEWAY, UNEXPECTED_NOT_MODIFIED.to_string()),
+            CacheError::Shared(e) => return ApiError::from(&**e),
+            CacheError::Redis(e) => ApiError::Custom(StatusCode::INTERNAL_SERVER_ERROR, format!("redis error: {}", e)),
+            CacheError::Reqwest(e) => ApiError::Custom(StatusCode::INTERNAL_SERVER_ERROR, format!("HTTP request error: {}", e)),
+            CacheError::Serialization(e) => ApiError::Custom(StatusCode::INTERNAL_SERVER_ERROR, format!("JSON serialization error: {}", e)),
+            CacheError::Codec(e) => ApiError::Custom(StatusCode::INTERNAL_SERVER_ERROR, format!("cache codec error: {}", e)),
+        };
+        debug!("Shared cache error: {:#?}", err);
+        error
+    }
+}
+
 impl IntoResponse for ApiError {
     fn into_response(self) -> Response {
         let status = self.status_code();
         let message = self.message();
         let response = ApiResponse::<()>::error(&message, status);
-        (status, Json(response)).into_response()
+        let mut response = (status, Json(response)).into_response();
+        if let ApiError::RateLimited(retry_after_secs) = self {
+            response.headers_mut().insert(RETRY_AFTER, HeaderValue::from(retry_after_secs));
+        }
+        response
     }
 }
@@ -0,0 +1,472 @@
+use std::{fmt, str::FromStr};
+
+use axum::{
+    extract::{rejection::{PathRejection, QueryRejection}, Path, Query},
+    http::StatusCode,
+    response::IntoResponse,
+    Extension,
+    Json,
+};
+
+use bb8_redis::redis::{self, AsyncCommands, InfoDict, Value};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::{
+    error::ApiError,
+    response::ApiResponse,
+    util::{
+        cache::{delete_key, CacheFlights, MemoryCache, NOT_FOUND_MARKER},
+        cache_key::{glob_match, is_cache_key},
+        codec::{self, CodecKind},
+        compression,
+        invalidation::{invalidate_matching, Invalidation, Invalidator},
+        redis_connection::ScanCursor,
+        redis_pool::RedisPool,
+    },
+};
+
+/// Keys returned per page when `count` is not given
+const DEFAULT_PAGE_SIZE: usize = 100;
+
+/// Upper bound for `count`
+const MAX_PAGE_SIZE: usize = 1000;
+
+/// `COUNT` hint passed to each `SCAN` call
+const SCAN_BATCH: usize = 500;
+
+/// `SCAN` calls per list request, so a narrow prefix over a large keyspace returns
+/// a short page with `next_cursor` instead of walking the whole keyspace at once
+const MAX_SCAN_CALLS: usize = 20;
+
+/// `SCAN` calls per purge request, a broad pattern is purged over several requests
+const MAX_PURGE_SCAN_CALLS: usize = 100;
+
+#[derive(Deserialize)]
+pub struct ListKeysQuery {
+    #[serde(default)]
+    pub prefix: String,
+    pub cursor: Option<String>,
+    pub count: Option<usize>,
+}
+
+#[derive(Deserialize)]
+pub struct PurgeQuery {
+    pub pattern: String,
+    pub cursor: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct KeyPage {
+    pub keys: Vec<KeySummary>,
+    pub next_cursor: String, // "0" once the scan is complete
+}
+
+#[derive(Serialize)]
+pub struct KeySummary {
+    pub key: String,
+    pub in_memory: bool,
+}
+
+#[derive(Serialize)]
+pub struct KeyDetails {
+    pub key: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub redis: Option<RedisEntry>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory: Option<MemoryEntryInfo>,
+}
+
+#[derive(Serialize)]
+pub struct RedisEntry {
+    pub ttl_ms: i64,
+    pub size_bytes: usize,
+    pub compressed: bool,
+    pub not_found: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub codec: Option<CodecKind>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fresh_until: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub validators: Option<serde_json::Value>, // Upstream ETag / Last-Modified used to revalidate
+    pub value: serde_json::Value, // null for markers and bincode, which is not self-describing
+}
+
+#[derive(Serialize)]
+pub struct MemoryEntryInfo {
+    pub ttl_ms: u128,
+    pub not_found: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fresh_until: Option<i64>,
+}
+
+#[derive(Serialize)]
+pub struct PurgeReport {
+    pub redis_deleted: usize,
+    pub memory_invalidated: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>, // Pattern purges only, "0" once every matching key is purged
+}
+
+#[derive(Serialize)]
+pub struct CacheStats {
+    pub memory: MemoryStats,
+    pub redis: RedisStats,
+    pub in_flight_fetches: usize,
+}
+
+#[derive(Serialize)]
+pub struct MemoryStats {
+    pub entries: u64,
+    pub weighted_size: u64,
+}
+
+#[derive(Serialize)]
+pub struct RedisStats {
+    pub degraded: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keys: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub used_memory_bytes: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keyspace_hits: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keyspace_misses: Option<u64>,
+}
+
+/// Lists cache keys starting with `prefix`, paginated with the Redis `SCAN` cursor
+pub async fn cache_keys_handler_get(
+    query: Result<Query<ListKeysQuery>, QueryRejection>,
+    Extension(redis_pool): Extension<RedisPool>,
+    Extension(moka_cache): Extension<MemoryCache>,
+) -> Result<impl IntoResponse, ApiError> {
+    let Query(query) = query?;
+    let mut cursor = parse_cursor(query.cursor.as_deref())?;
+    let count = query.count.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+    let pattern = format!("{}*", escape_glob(&query.prefix));
+
+    let mut conn = redis_pool.get().await.ok_or_else(redis_unavailable)?;
+    let mut keys = Vec::new();
+
+    // SCAN may return fewer keys than asked for, or none, before the cursor wraps around
+    for _ in 0..MAX_SCAN_CALLS {
+        let (next, batch) = conn
+            .scan_match(cursor.scan, &pattern, SCAN_BATCH)
+            .await
+            .inspect_err(|e| redis_pool.report_error(e))?;
+
+        let batch: Vec<String> = batch.into_iter().filter(|key| is_cache_key(key)).collect();
+        let take = count - keys.len();
+        let unread = batch.len().saturating_sub(cursor.skip);
+        for key in batch.into_iter().skip(cursor.skip).take(take) {
+            let in_memory = moka_cache.contains_key(&key);
+            keys.push(KeySummary { key, in_memory });
+        }
+
+        // A batch overflowing the page is scanned again by the next request, past the keys already returned
+        cursor = match unread > take {
+            true => PageCursor { scan: cursor.scan, skip: cursor.skip + take },
+            false => PageCursor { scan: next, skip: 0 },
+        };
+        if cursor.is_complete() || keys.len() >= count {
+            break;
+        }
+    }
+
+    let page = KeyPage { keys, next_cursor: cursor.to_string() };
+    Ok((StatusCode::OK, Json(ApiResponse::success(page))))
+}
+
+/// Shows a key's value, TTL and encoding in Redis, and its entry in this instance's memory tier
+pub async fn cache_key_handler_get(
+    key: Result<Path<String>, PathRejection>,
+    Extension(redis_pool): Extension<RedisPool>,
+    Extension(moka_cache): Extension<MemoryCache>,
+) -> Result<impl IntoResponse, ApiError> {
+    let Path(key) = key?;
+    if !is_cache_key(&key) {
+        return Err(ApiError::Custom(StatusCode::BAD_REQUEST, format!("'{}' is not a cache key", key)));
+    }
+
+    let memory = moka_cache.get(&key).await.map(|entry| MemoryEntryInfo {
+        ttl_ms: entry.ttl().as_millis(),
+        not_found: entry.fresh_until().is_none(),
+        fresh_until: entry.fresh_until(),
+    });
+
+    let redis = match redis_pool.get().await {
+        Some(mut conn) => {
+            let (bytes, ttl_ms): (Option<Vec<u8>>, i64) = redis::pipe()
+                .get(&key)
+                .pttl(&key)
+                .query_async(&mut *conn)
+                .await
+                .inspect_err(|e| redis_pool.report_error(e))?;
+            bytes.map(|bytes| describe_redis_entry(bytes, ttl_ms))
+        }
+        None => None,
+    };
+
+    if redis.is_none() && memory.is_none() {
+        return Err(ApiError::NotFound(format!("cache key '{}' not found", key)));
+    }
+
+    let details = KeyDetails { key, redis, memory };
+    Ok((StatusCode::OK, Json(ApiResponse::success(details))))
+}
+
+/// Purges a single key from Redis and from the memory tier of every instance
+pub async fn cache_key_handler_delete(
+    key: Result<Path<String>, PathRejection>,
+    Extension(redis_pool): Extension<RedisPool>,
+    Extension(moka_cache): Extension<MemoryCache>,
+    Extension(invalidator): Extension<Invalidator>,
+) -> Result<impl IntoResponse, ApiError> {
+    let Path(key) = key?;
+    if !is_cache_key(&key) {
+        return Err(ApiError::Custom(StatusCode::BAD_REQUEST, format!("'{}' is not a cache key", key)));
+    }
+
+    let memory_invalidated = usize::from(moka_cache.contains_key(&key));
+    let redis_deleted = usize::from(delete_key(&redis_pool, &moka_cache, Some(&invalidator), &key).await);
+    info!("Purged cache key '{}'", key);
+
+    let report = PurgeReport { redis_deleted, memory_invalidated, next_cursor: None };
+    Ok((StatusCode::OK, Json(ApiResponse::success(report))))
+}
+
+/// Purges every cache key matching a `*`/`?` glob from Redis and from the memory tier of every instance.
+/// A purge scans a bounded part of the keyspace, pass `next_cursor` back until it is `0`
+pub async fn cache_keys_handler_delete(
+    query: Result<Query<PurgeQuery>, QueryRejection>,
+    Extension(redis_pool): Extension<RedisPool>,
+    Extension(moka_cache): Extension<MemoryCache>,
+    Extension(invalidator): Extension<Invalidator>,
+) -> Result<impl IntoResponse, ApiError> {
+    let Query(PurgeQuery { pattern, cursor }) = query?;
+    if pattern.is_empty() || pattern.contains(['[', ']', '\\']) {
+        return Err(ApiError::Custom(
+            StatusCode::BAD_REQUEST,
+            "pattern must be non-empty and may only use '*' and '?' wildcards".to_string(),
+        ));
+    }
+
+    let mut cursor = parse_cursor(cursor.as_deref())?.scan;
+
+    let mut redis_deleted = 0;
+    if let Some(mut conn) = redis_pool.get().await {
+        for _ in 0..MAX_PURGE_SCAN_CALLS {
+            let (next, batch) = conn
+                .scan_match(cursor, &pattern, SCAN_BATCH)
+                .await
+                .inspect_err(|e| redis_pool.report_error(e))?;
+
+            // Rate limit buckets, nonces and locks can match a broad pattern too
+            let batch: Vec<String> = batch
+                .into_iter()
+                .filter(|key| is_cache_key(key) && glob_match(&pattern, key))
+                .collect();
+            if !batch.is_empty() {
+                let deleted: usize = conn
+                    .unlink(&batch)
+                    .await
+                    .inspect_err(|e| redis_pool.report_error(e))?;
+                redis_deleted += deleted;
+            }
+
+            cursor = next;
+            if cursor.is_complete() {
+                break;
+            }
+        }
+    }
+
+    let memory_invalidated = invalidate_matching(&moka_cache, &pattern).await;
+    invalidator.publish(Invalidation::Pattern { pattern: pattern.clone() }).await;
+    info!(
+        "Purged cache keys matching '{}': {} in Redis, {} in memory",
+        pattern, redis_deleted, memory_invalidated
+    );
+
+    let report = PurgeReport { redis_deleted, memory_invalidated, next_cursor: Some(cursor.to_string()) };
+    Ok((StatusCode::OK, Json(ApiResponse::success(report))))
+}
+
+/// Aggregate statistics of both cache tiers
+pub async fn cache_stats_handler_get(
+    Extension(redis_pool): Extension<RedisPool>,
+    Extension(moka_cache): Extension<MemoryCache>,
+    Extension(cache_flights): Extension<CacheFlights>,
+) -> Result<impl IntoResponse, ApiError> {
+    moka_cache.run_pending_tasks().await;
+
+    let mut redis = RedisStats {
+        degraded: redis_pool.is_degraded(),
+        keys: None,
+        used_memory_bytes: None,
+        keyspace_hits: None,
+        keyspace_misses: None,
+    };
+
+    if let Some(mut conn) = redis_pool.get().await {
+        // Sent separately, in a cluster both are fanned out to every primary
+        let keys: u64 = redis::cmd("DBSIZE")
+            .query_async(&mut *conn)
+            .await
+            .inspect_err(|e| redis_pool.report_error(e))?;
+        let info: Value = redis::cmd("INFO")
+            .query_async(&mut *conn)
+            .await
+            .inspect_err(|e| redis_pool.report_error(e))?;
+        let infos = node_infos(info)?;
+        let sum = |field: &str| infos.iter().map(|info| info.get::<u64>(field)).sum::<Option<u64>>();
+
+        redis.keys = Some(keys);
+        redis.used_memory_bytes = sum("used_memory");
+        redis.keyspace_hits = sum("keyspace_hits");
+        redis.keyspace_misses = sum("keyspace_misses");
+    }
+
+    let stats = CacheStats {
+        memory: MemoryStats {
+            entries: moka_cache.entry_count(),
+            weighted_size: moka_cache.weighted_size(),
+        },
+        redis,
+        in_flight_fetches: cache_flights.in_flight(),
+    };
+    Ok((StatusCode::OK, Json(ApiResponse::success(stats))))
+}
+
+fn describe_redis_entry(bytes: Vec<u8>, ttl_ms: i64) -> RedisEntry {
+    let size_bytes = bytes.len();
+    let compressed = compression::is_compressed(&bytes);
+    let mut entry = RedisEntry {
+        ttl_ms,
+        size_bytes,
+        compressed,
+        not_found: false,
+        codec: None,
+        fresh_until: None,
+        validators: None,
+        value: serde_json::Value::Null,
+    };
+
+    let Ok(bytes) = compression::decode(bytes) else {
+        return entry;
+    };
+    if bytes == NOT_FOUND_MARKER {
+        entry.not_found = true;
+        return entry;
+    }
+
+    let codec = codec::detect(&bytes);
+    entry.codec = Some(codec);
+    if codec != CodecKind::Bincode {
+        if let Ok(serde_json::Value::Object(mut envelope)) = codec::decode::<serde_json::Value>(&bytes) {
+            entry.fresh_until = envelope.get("fresh_until").and_then(|v| v.as_i64());
+            entry.validators = envelope.remove("validators").filter(|v| !v.is_null());
+            entry.value = envelope.remove("data").unwrap_or_default();
+        }
+    }
+    entry
+}
+
+/// Splits an `INFO` reply, which a cluster returns as a map of node address to report
+fn node_infos(info: Value) -> redis::RedisResult<Vec<InfoDict>> {
+    match info {
+        Value::Map(nodes) => nodes
+            .into_iter()
+            .map(|(_, info)| redis::from_owned_redis_value(info))
+            .collect(),
+        info => Ok(vec![redis::from_owned_redis_value(info)?]),
+    }
+}
+
+/// Escapes Redis glob metacharacters so a prefix is matched literally
+fn escape_glob(prefix: &str) -> String {
+    let mut escaped = String::with_capacity(prefix.len());
+    for c in prefix.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn parse_cursor(cursor: Option<&str>) -> Result<PageCursor, ApiError> {
+    match cursor.map(str::parse::<PageCursor>) {
+        None => Ok(PageCursor::START),
+        Some(Ok(cursor)) => Ok(cursor),
+        Some(Err(_)) => Err(ApiError::Custom(StatusCode::BAD_REQUEST, "invalid cursor".to_string())),
+    }
+}
+
+/// Position of a key listing, a scan cursor plus the keys of its next batch already returned,
+/// rendered `{scan}` or `{scan}.{skip}`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct PageCursor {
+    scan: ScanCursor,
+    skip: usize,
+}
+
+impl PageCursor {
+    const START: PageCursor = PageCursor { scan: ScanCursor::START, skip: 0 };
+
+    fn is_complete(&self) -> bool {
+        *self == Self::START
+    }
+}
+
+impl fmt::Display for PageCursor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.skip {
+            0 => write!(f, "{}", self.scan),
+            skip => write!(f, "{}.{}", self.scan, skip),
+        }
+    }
+}
+
+impl FromStr for PageCursor {
+    type Err = std::num::ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('.') {
+            Some((scan, skip)) => Ok(PageCursor { scan: scan.parse()?, skip: skip.parse()? }),
+            None => Ok(PageCursor { scan: s.parse()?, skip: 0 }),
+        }
+    }
+}
+
+fn redis_unavailable() -> ApiError {
+    ApiError::Custom(StatusCode::SERVICE_UNAVAILABLE, "redis is unavailable".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn page_cursor_round_trips() {
+        for text in ["0", "17", "0.5", "17.250", "2-0", "3-42.9"] {
+            let cursor: PageCursor = text.parse().unwrap();
+            assert_eq!(cursor.to_string(), text);
+        }
+        assert_eq!("17.0".parse::<PageCursor>().unwrap().to_string(), "17");
+    }
+
+    #[test]
+    fn page_cursor_completes_only_at_start_of_a_batch() {
+        assert!("0".parse::<PageCursor>().unwrap().is_complete());
+        assert!(!"0.5".parse::<PageCursor>().unwrap().is_complete());
+        assert!(!"17".parse::<PageCursor>().unwrap().is_complete());
+    }
+
+    #[test]
+    fn page_cursor_rejects_garbage() {
+        for text in ["", ".", "1.", ".1", "1.2.3", "1.-2", "x.1"] {
+            assert!(text.parse::<PageCursor>().is_err(), "{:?} parsed", text);
+        }
+    }
+}
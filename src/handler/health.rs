@@ -1,11 +1,206 @@
-use axum::{response::IntoResponse, Json};
+use std::time::{Duration, Instant};
+
+use axum::{http::StatusCode, response::IntoResponse, Extension, Json};
+use reqwest::Client;
+use serde::Serialize;
 
 use crate::{
-    response::ApiResponse
+    response::ApiResponse,
+    service::warmup::CacheWarmer,
+    util::{build_info::BuildInfo, cache_context::CacheContext, redis_pool::RedisPool, shutdown::Shutdown},
 };
 
-pub async fn health_checker_handler() -> impl IntoResponse {
-    const MESSAGE: &str = "Hello from rust-backend core!";
-    let response: ApiResponse<()> = ApiResponse::message_only(MESSAGE);
-    Json(response)
+#[derive(Serialize)]
+pub struct LivenessReport {
+    pub version: &'static str,
+    pub git_sha: &'static str,
+    pub uptime_secs: u64,
+}
+
+#[derive(Serialize)]
+pub struct ReadinessReport {
+    pub ready: bool,
+    pub draining: bool,
+    pub version: &'static str,
+    pub git_sha: &'static str,
+    pub uptime_secs: u64,
+    pub checks: DependencyChecks,
+}
+
+#[derive(Serialize)]
+pub struct DependencyChecks {
+    pub redis: RedisCheck,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub redis_replicas: Option<RedisCheck>, // Informational, reads fall back to the primary
+    pub upstream: UpstreamCheck,
+    pub upstream_circuits: Vec<CircuitCheck>, // Informational, an open circuit still serves cached values
+    pub memory_cache: MemoryCacheCheck,
+    pub cache_warmup: CacheWarmupCheck,
+}
+
+#[derive(Serialize)]
+pub struct RedisCheck {
+    pub status: &'static str,
+    pub connections: u32,
+    pub idle_connections: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<u128>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct UpstreamCheck {
+    pub status: &'static str,
+    pub url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub http_status: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<u128>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct CircuitCheck {
+    pub host: String,
+    pub state: &'static str,
+}
+
+#[derive(Serialize)]
+pub struct MemoryCacheCheck {
+    pub status: &'static str,
+    pub entries: u64,
+}
+
+#[derive(Serialize)]
+pub struct CacheWarmupCheck {
+    pub status: &'static str,
+}
+
+/// Liveness probe: the process is up and serving, dependencies are not checked
+pub async fn health_live_handler(
+    Extension(build_info): Extension<BuildInfo>,
+) -> impl IntoResponse {
+    let report = LivenessReport {
+        version: build_info.version,
+        git_sha: build_info.git_sha,
+        uptime_secs: build_info.uptime().as_secs(),
+    };
+    Json(ApiResponse::success(report))
+}
+
+/// Readiness probe: checks Redis, the upstream, the in-memory cache and its warm-up, 503 when not ready
+pub async fn health_ready_handler(
+    Extension(shutdown): Extension<Shutdown>,
+    Extension(cache): Extension<CacheContext>,
+    Extension(cache_warmer): Extension<CacheWarmer>,
+    Extension(build_info): Extension<BuildInfo>,
+) -> impl IntoResponse {
+    let (redis_pool, http_client, config) = (cache.backend(), cache.http_client(), cache.config());
+    let timeout = Duration::from_millis(config.health.check_timeout_ms);
+
+    let (redis, redis_replicas, upstream) = tokio::join!(
+        check_redis(redis_pool, timeout),
+        async {
+            match redis_pool.replicas() {
+                Some(replicas) => Some(check_redis(replicas, timeout).await),
+                None => None,
+            }
+        },
+        check_upstream(http_client, &config.health.upstream_url, timeout),
+    );
+
+    let upstream_circuits = cache
+        .circuit_breakers()
+        .states()
+        .into_iter()
+        .map(|(host, state)| CircuitCheck { host, state: state.as_str() })
+        .collect();
+
+    let memory_cache = MemoryCacheCheck {
+        status: "ok",
+        entries: cache.moka_cache().entry_count(),
+    };
+
+    let warmed = cache_warmer.is_warmed();
+    let cache_warmup = CacheWarmupCheck {
+        status: if warmed { "ok" } else { "pending" },
+    };
+
+    let draining = shutdown.is_draining();
+    let redis_ready = redis.status == "ok" || !config.health.require_redis;
+    let ready = !draining && redis_ready && warmed && upstream.status == "ok";
+
+    let report = ReadinessReport {
+        ready,
+        draining,
+        version: build_info.version,
+        git_sha: build_info.git_sha,
+        uptime_secs: build_info.uptime().as_secs(),
+        checks: DependencyChecks {
+            redis,
+            redis_replicas,
+            upstream,
+            upstream_circuits,
+            memory_cache,
+            cache_warmup,
+        },
+    };
+
+    if ready {
+        (StatusCode::OK, Json(ApiResponse::success(report)))
+    } else {
+        let message = if draining { "shutting down" } else { "not ready" };
+        let code = StatusCode::SERVICE_UNAVAILABLE;
+        (code, Json(ApiResponse::error_with_data(report, message, code)))
+    }
+}
+
+async fn check_redis(redis_pool: &RedisPool, timeout: Duration) -> RedisCheck {
+    let state = redis_pool.pool().state();
+    let started = Instant::now();
+
+    let (status, latency_ms, error) = match tokio::time::timeout(timeout, redis_pool.ping()).await {
+        Ok(Ok(())) if redis_pool.is_degraded() => ("degraded", Some(started.elapsed().as_millis()), None),
+        Ok(Ok(())) => ("ok", Some(started.elapsed().as_millis()), None),
+        Ok(Err(e)) => ("down", None, Some(e)),
+        Err(_) => ("down", None, Some("ping timed out".to_string())),
+    };
+
+    RedisCheck {
+        status,
+        connections: state.connections,
+        idle_connections: state.idle_connections,
+        latency_ms,
+        error,
+    }
+}
+
+async fn check_upstream(http_client: &Client, url: &str, timeout: Duration) -> UpstreamCheck {
+    let started = Instant::now();
+
+    let (status, http_status, latency_ms, error) = match http_client.head(url).timeout(timeout).send().await {
+        Ok(response) if response.status().is_server_error() => (
+            "down",
+            Some(response.status().as_u16()),
+            Some(started.elapsed().as_millis()),
+            None,
+        ),
+        Ok(response) => (
+            "ok",
+            Some(response.status().as_u16()),
+            Some(started.elapsed().as_millis()),
+            None,
+        ),
+        Err(e) => ("down", None, None, Some(e.to_string())),
+    };
+
+    UpstreamCheck {
+        status,
+        url: url.to_string(),
+        http_status,
+        latency_ms,
+        error,
+    }
 }
@@ -0,0 +1,19 @@
+use axum::{http::header::CONTENT_TYPE, response::IntoResponse, Extension};
+use metrics::gauge;
+use metrics_exporter_prometheus::PrometheusHandle;
+
+use crate::util::{cache::CacheFlights, metrics::record_pool_state, redis_pool::RedisPool};
+
+/// Serves all metrics in the Prometheus text exposition format
+pub async fn metrics_handler(
+    Extension(prometheus): Extension<PrometheusHandle>,
+    Extension(redis_pool): Extension<RedisPool>,
+    Extension(cache_flights): Extension<CacheFlights>,
+) -> impl IntoResponse {
+    record_pool_state(&redis_pool);
+    gauge!("cache_inflight_fetches").set(cache_flights.in_flight() as f64);
+    (
+        [(CONTENT_TYPE, "text/plain; version=0.0.4")],
+        prometheus.render(),
+    )
+}
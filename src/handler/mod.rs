@@ -1,7 +1,20 @@
+mod cache_admin;
 mod health;
+mod metrics;
 mod user;
 
-pub use health::health_checker_handler;
+pub use cache_admin::{
+    cache_keys_handler_get,
+    cache_keys_handler_delete,
+    cache_key_handler_get,
+    cache_key_handler_delete,
+    cache_stats_handler_get
+};
+pub use health::{
+    health_live_handler,
+    health_ready_handler
+};
+pub use metrics::metrics_handler;
 pub use user::{
     users_handler_get,
     user_id_handler_get
@@ -6,79 +6,75 @@ use axum::{
     Extension,
 };
 
-use bb8_redis::{bb8::Pool, RedisConnectionManager};
-use moka::future::Cache;
 use reqwest::Client;
 
 use crate::{
     error::ApiError,
     response::ApiResponse,
-    util::cache::{CacheWrapper, JsonResponseExt},
+    util::{cache_backend::CacheBackend, cache_context::CacheContext},
     cache_http_request,
 };
-use crate::model::User;
+use crate::model::{UserKey, UsersKey};
+use crate::service::user::{fetch_user, fetch_users, UPSTREAM_HOST};
 
 /// Handles GET requests for all users from JSONPlaceholder
-pub async fn users_handler_get(
-    Extension(redis_pool): Extension<Pool<RedisConnectionManager>>,
-    Extension(moka_cache): Extension<Cache<String, String>>,
-    Extension(http_client): Extension<Client>,
+pub async fn users_handler_get<B: CacheBackend>(
+    Extension(cache): Extension<CacheContext<B>>,
 ) -> Result<impl IntoResponse, ApiError> {
     // Create a cache wrapper for User vector
-    let cache = CacheWrapper::<Vec<User>>::new(
-        redis_pool,
-        moka_cache,
-        10,
-        http_client,
-    );
+    let retry = cache.retry().clone();
+    let cache = cache.wrapper::<UsersKey>(UPSTREAM_HOST);
 
     // Attempt to fetch users from cache or JSONPlaceholder API
-    let users = cache_http_request!(
-        cache, 
-        "users:all",
-        |client: Client| async move {
-            client.get("https://jsonplaceholder.typicode.com/users")
-                .send()
-                .await?
-                .json_cached::<Vec<User>>()
-                .await
-        }
-    )?;
+    let users = cache_http_request!(cache, &UsersKey, move |client: Client, validators| fetch_users(client, retry, validators))?;
 
     let response = ApiResponse::success(users);
     Ok((StatusCode::OK, Json(response)))
 }
 
 /// Handles GET requests for a specific user by ID from JSONPlaceholder
-pub async fn user_id_handler_get(
+pub async fn user_id_handler_get<B: CacheBackend>(
     id: Result<Path<i32>, PathRejection>,
-    Extension(redis_pool): Extension<Pool<RedisConnectionManager>>,
-    Extension(moka_cache): Extension<Cache<String, String>>,
-    Extension(http_client): Extension<Client>,
+    Extension(cache): Extension<CacheContext<B>>,
 ) -> Result<impl IntoResponse, ApiError> {
     let Path(id) = id.map_err(|e| ApiError::Conflict(e.to_string()))?;
 
     // Create a cache wrapper for a single User
-    let cache = CacheWrapper::<User>::new(
-        redis_pool,
-        moka_cache,
-        10,
-        http_client,
-    );
+    let retry = cache.retry().clone();
+    let cache = cache.wrapper::<UserKey>(UPSTREAM_HOST);
 
     // Attempt to fetch the user from cache or JSONPlaceholder API
-    let user = cache_http_request!(
-        cache, 
-        &format!("user:{}", id),
-        |client: Client| async move {
-            client.get(format!("https://jsonplaceholder.typicode.com/users/{}", id))
-                .send()
-                .await?
-                .json_cached::<User>()
-                .await
-        }
-    )?;
+    let user = cache_http_request!(cache, &UserKey(id), move |client: Client, validators| fetch_user(client, retry, id, validators))?;
 
     let response = ApiResponse::success(user);
     Ok((StatusCode::OK, Json(response)))
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::body::to_bytes;
+    use moka::future::Cache;
+
+    use super::*;
+    use crate::{
+        config::AppConfig,
+        model::User,
+        util::{cache::MemoryExpiry, cache_backend::MemoryBackend},
+    };
+
+    #[tokio::test]
+    async fn users_are_served_from_the_cache_without_redis() {
+        let moka_cache = Cache::builder().expire_after(MemoryExpiry).build();
+        let cache = CacheContext::new(MemoryBackend::new(), moka_cache, Client::new(), Arc::new(AppConfig::default()));
+        cache.wrapper::<UsersKey>(UPSTREAM_HOST).set(&UsersKey, Vec::<User>::new()).await.unwrap();
+
+        let response = users_handler_get(Extension(cache)).await.unwrap().into_response();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["data"], serde_json::json!([]));
+    }
+}
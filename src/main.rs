@@ -1,3 +1,4 @@
+mod config;
 mod route;
 mod middleware;
 mod error;
@@ -6,20 +7,17 @@ mod model;
 mod response;
 mod util;
 
-use std::env;
+use std::future::IntoFuture;
+use std::sync::Arc;
 use std::time::Duration;
 
-use bb8_redis::RedisConnectionManager;
-use bb8_redis::bb8;
-
-use redis::AsyncCommands;
 use moka::future::Cache;
 use reqwest::ClientBuilder;
 
 mod service;
 
 use axum::{
-    http::{header::{ACCEPT, CONTENT_TYPE}, HeaderName, HeaderValue, Method},
+    http::{header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE, RETRY_AFTER}, HeaderName, HeaderValue, Method},
     extract::Extension,
 };
 use route::create_router;
@@ -33,9 +31,19 @@ use tower_http::{
 use sentry::{ClientOptions, IntoDsn};
 use sentry_tower::NewSentryLayer;
 
-use tracing::info;
+use tracing::{info, warn};
 use tracing_subscriber::{fmt, EnvFilter};
-use crate::middleware::{cache_header_middleware, process_time_middleware};
+use crate::config::AppConfig;
+use crate::service::warmup::CacheWarmer;
+use crate::util::{
+    build_info::BuildInfo,
+    cache::{MemoryCache, MemoryExpiry},
+    cache_context::CacheContext,
+    invalidation::Invalidator,
+    redis_pool::RedisPool,
+    shutdown::Shutdown,
+};
+use crate::middleware::{cache_header_middleware, metrics_middleware, process_time_middleware};
 
 #[allow(warnings, unused)]
 use crate::middleware::request_id_middleware;
@@ -52,65 +60,104 @@ async fn main() {
         .with_span_events(fmt::format::FmtSpan::CLOSE)
         .init();
 
-    let _dsn = env::var("SENTRY_DSN").unwrap_or_else(|_| "".to_string());
-    let _guard = sentry::init((
-        _dsn.into_dsn().unwrap(),
+    let config = match AppConfig::load() {
+        Ok(config) => Arc::new(config),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let sentry_guard = sentry::init((
+        config.sentry.dsn.as_str().into_dsn().unwrap(),
         ClientOptions {
             release: sentry::release_name!(),
-            traces_sample_rate: 0.2,
+            traces_sample_rate: config.sentry.traces_sample_rate,
             ..Default::default()
         },
     ));
 
-    let cors_host = env::var("CORS_HOST").unwrap_or_else(|_| "http://localhost:3000".to_string());
-
     let cors = CorsLayer::new()
-        .allow_origin(cors_host.parse::<HeaderValue>().unwrap())
+        .allow_origin(config.cors.allow_origin.parse::<HeaderValue>().unwrap())
         .allow_methods([Method::GET, Method::POST, Method::PATCH, Method::DELETE])
         .allow_credentials(true)
         .allow_headers([
             ACCEPT,
+            AUTHORIZATION,
             CONTENT_TYPE,
             HeaderName::from_static("x-timestamp"),
+            HeaderName::from_static("x-signature"),
+            HeaderName::from_static("x-key-id"),
+            HeaderName::from_static("x-nonce"),
+            HeaderName::from_bytes(config.rate_limit.api_key_header.as_bytes()).unwrap(),
+        ])
+        .expose_headers([
+            RETRY_AFTER,
+            HeaderName::from_static("ratelimit-limit"),
+            HeaderName::from_static("ratelimit-remaining"),
+            HeaderName::from_static("ratelimit-reset"),
+            HeaderName::from_static("ratelimit-policy"),
         ]);
 
-    let moka_cache: Cache<String, String> = Cache::builder()
-        .time_to_live(Duration::from_secs(10))
-        .max_capacity(16_000)
+    // Every entry expires after its own TTL, see CachePolicy
+    let moka_cache: MemoryCache = Cache::builder()
+        .expire_after(MemoryExpiry)
+        .max_capacity(config.cache.memory_capacity)
         .build();
 
-    let redis_url = env::var("REDIS_URL").unwrap_or_else(|_| "redis://localhost".to_string());
-    let redis_manager = RedisConnectionManager::new(redis_url).unwrap();
-    let redis_pool = bb8::Pool::builder()
-        .max_size((num_cpus::get() * 10) as u32)
-        .min_idle((num_cpus::get() * 2 + 1) as u32)
-        .max_lifetime(None)
-        .connection_timeout(Duration::from_millis(2000))
-        .idle_timeout(Some(Duration::from_secs(60)))
-        .build(redis_manager)
+    // Background tasks are spawned through it and stopped once the server is done
+    let shutdown = Shutdown::new();
+    tokio::spawn(shutdown.clone().listen_for_signals());
+
+    let redis_pool = RedisPool::connect(&config.redis)
         .await
-        .unwrap();
+        .expect("Failed to create Redis pool");
+    redis_pool.spawn_reconnect(Duration::from_secs(config.redis.reconnect_interval_secs), &shutdown);
 
-    {
-        let mut conn = redis_pool.get().await.unwrap();
-        let _: () = conn.set("health_check", "ok").await.unwrap();
-    }
+    let invalidator = Invalidator::new(redis_pool.clone(), config.cache.invalidation_channel.clone());
+    invalidator.spawn_subscriber(
+        moka_cache.clone(),
+        Duration::from_secs(config.redis.reconnect_interval_secs),
+        &shutdown,
+    );
 
     let http_client = ClientBuilder::new()
-        .timeout(Duration::from_secs(30))
-        .connect_timeout(Duration::from_secs(10))
-        .pool_max_idle_per_host(10)
-        .pool_idle_timeout(Duration::from_secs(60))
+        .timeout(Duration::from_secs(config.http_client.timeout_secs))
+        .connect_timeout(Duration::from_secs(config.http_client.connect_timeout_secs))
+        .pool_max_idle_per_host(config.http_client.pool_max_idle_per_host)
+        .pool_idle_timeout(Duration::from_secs(config.http_client.pool_idle_timeout_secs))
         .user_agent(format!("{}/{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")))
         .gzip(true)
         .build()
         .expect("Failed to create HTTP client");
 
+    if config.signing.enabled && config.signing.keys.is_empty() {
+        warn!("Request signing is enabled but no keys are configured, protected routes will reject every request");
+    }
+    if config.admin.tokens.is_empty() {
+        warn!("No admin tokens are configured, the admin API will reject every request");
+    }
+
+    let prometheus = util::metrics::install(&shutdown);
+
+    let cache_context = CacheContext::new(redis_pool.clone(), moka_cache.clone(), http_client, config.clone())
+        .with_invalidator(invalidator.clone())
+        .with_task_tracker(shutdown.tasks().clone());
+
+    // Warm-up runs while the server is already live, readiness waits for it
+    let cache_warmer = CacheWarmer::new(cache_context.clone());
+    shutdown.spawn({
+        let cache_warmer = cache_warmer.clone();
+        async move { cache_warmer.warm_up().await }
+    });
+    cache_warmer.spawn_hot_refresh(&shutdown);
+
     let middleware_stack = ServiceBuilder::new()
         .layer(NewSentryLayer::new_from_top())
         .layer(TraceLayer::new_for_http())
         .layer(cors)
-        .layer(tower::limit::ConcurrencyLimitLayer::new(1000))
+        .layer(tower::limit::ConcurrencyLimitLayer::new(config.server.concurrency_limit))
+        .layer(axum::middleware::from_fn(metrics_middleware))
         .layer(axum::middleware::from_fn(process_time_middleware))
         .layer(axum::middleware::from_fn(cache_header_middleware));
 
@@ -120,21 +167,64 @@ async fn main() {
 
     let app = create_router()
         .layer(middleware_stack)
-        .layer(Extension(redis_pool))
+        .layer(Extension(redis_pool.clone()))
         .layer(Extension(moka_cache))
-        .layer(Extension(http_client));
-
-    let _bind = env::var("SERVER_BIND").unwrap_or_else(|_| "0.0.0.0:8000".to_string());
-    let listener = tokio::net::TcpListener::bind(&_bind)
+        .layer(Extension(cache_context.flights().clone()))
+        .layer(Extension(cache_context))
+        .layer(Extension(cache_warmer))
+        .layer(Extension(invalidator))
+        .layer(Extension(config.clone()))
+        .layer(Extension(shutdown.clone()))
+        .layer(Extension(BuildInfo::new()))
+        .layer(Extension(prometheus));
+
+    let listener = tokio::net::TcpListener::bind(&config.server.bind)
         .await
         .unwrap();
 
-    info!("🚀 Server started successfully on {}", _bind);
+    info!("🚀 Server started successfully on {}", config.server.bind);
 
-    axum::serve(
+    let shutdown_delay = Duration::from_secs(config.server.shutdown_delay_secs);
+    let shutdown_timeout = Duration::from_secs(config.server.shutdown_timeout_secs);
+
+    let mut server = Box::pin(axum::serve(
         listener,
         app.into_make_service_with_connect_info::<std::net::SocketAddr>()
     )
-        .await
-        .unwrap();
+        .with_graceful_shutdown({
+            let shutdown = shutdown.clone();
+            async move {
+                shutdown.draining().await;
+                tokio::time::sleep(shutdown_delay).await;
+                info!("Draining in-flight requests for up to {}s", shutdown_timeout.as_secs());
+            }
+        })
+        .into_future());
+
+    tokio::select! {
+        result = &mut server => result.unwrap(),
+        _ = async {
+            shutdown.draining().await;
+            tokio::time::sleep(shutdown_delay + shutdown_timeout).await;
+        } => warn!("Drain deadline exceeded, aborting remaining in-flight requests"),
+    }
+
+    // The router's extensions hold the pool and the caches, background
+    // tasks may still be writing to Redis; both go before the pool
+    drop(server);
+    info!("Stopping background tasks for up to {}s", shutdown_timeout.as_secs());
+    if !shutdown.stop_tasks(shutdown_timeout).await {
+        warn!("Background tasks did not stop in time, abandoning them");
+    }
+
+    // Release remaining Redis connections before flushing pending Sentry events
+    let pool_state = redis_pool.pool().state();
+    info!(
+        "Closing Redis pool ({} connections, {} idle)",
+        pool_state.connections, pool_state.idle_connections
+    );
+    drop(redis_pool);
+    drop(sentry_guard);
+
+    info!("👋 Server stopped");
 }
@@ -0,0 +1,46 @@
+use std::sync::Arc;
+
+use axum::{
+    middleware::Next,
+    response::{IntoResponse, Response},
+    http::{header::{AUTHORIZATION, CACHE_CONTROL}, Request, HeaderValue, StatusCode},
+    body::Body,
+    Extension,
+};
+use crate::{config::AppConfig, error::ApiError, util::signing::matches_secret};
+
+/// Requires `authorization: Bearer <token>` with one of `admin.tokens` on top of
+/// the protected middlewares, so signing keys alone don't grant the admin API.
+/// Admin responses are never cached.
+pub async fn admin_guard_middleware(
+    Extension(config): Extension<Arc<AppConfig>>,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    let mut response = match authorize(&config, &request) {
+        Ok(()) => next.run(request).await,
+        Err(e) => e.into_response(),
+    };
+    response.headers_mut().insert(CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    response
+}
+
+fn authorize(config: &AppConfig, request: &Request<Body>) -> Result<(), ApiError> {
+    let tokens = &config.admin.tokens;
+    if tokens.is_empty() {
+        return Err(ApiError::Custom(StatusCode::FORBIDDEN, "admin API is not configured".to_string()));
+    }
+
+    let token = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .ok_or(ApiError::Unauthorized)?;
+
+    if matches_secret(token.trim(), tokens) {
+        Ok(())
+    } else {
+        Err(ApiError::Unauthorized)
+    }
+}
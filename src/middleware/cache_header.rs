@@ -1,21 +1,86 @@
+use std::sync::Arc;
+
 use axum::{
     middleware::Next,
     response::Response,
-    http::{Request, HeaderValue},
+    http::{Request, HeaderValue, StatusCode},
     body::Body,
+    Extension,
 };
 
+use crate::config::{AppConfig, CacheConfig};
+
 pub async fn cache_header_middleware(
+    Extension(config): Extension<Arc<AppConfig>>,
     request: Request<Body>,
     next: Next,
 ) -> Response {
-    let response = next.run(request).await;
+    let mut response = next.run(request).await;
+    set_cache_control(&mut response, &config.cache);
+    response
+}
 
-    let mut response = response;
-    response.headers_mut().insert(
-        "cache-control",
-        HeaderValue::from_str("public, max-age=10, stale-while-revalidate=10").unwrap(),
-    );
+/// Lets shared caches keep successful responses for the Redis TTL, and nothing else
+fn set_cache_control(response: &mut Response, config: &CacheConfig) {
+    let status = response.status();
+    // Rate limiting and outages must not be replayed by shared caches after they end
+    if matches!(status, StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE) {
+        response.headers_mut().insert("cache-control", HeaderValue::from_static("no-store"));
+        return;
+    }
 
-    response
+    // Routes that set their own policy, such as `no-store` on admin routes, keep it
+    if status.is_success() && !response.headers().contains_key("cache-control") {
+        response.headers_mut().insert(
+            "cache-control",
+            HeaderValue::from_str(&format!(
+                "public, max-age={}, stale-while-revalidate={}",
+                config.redis_ttl_secs, config.stale_ttl_secs
+            )).unwrap(),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::response::IntoResponse;
+
+    use super::*;
+
+    fn cache_control(response: impl IntoResponse) -> Option<String> {
+        let mut response = response.into_response();
+        set_cache_control(&mut response, &CacheConfig::default());
+        response
+            .headers()
+            .get("cache-control")
+            .map(|value| value.to_str().unwrap().to_string())
+    }
+
+    #[test]
+    fn only_successful_responses_are_public() {
+        let config = CacheConfig::default();
+        let public = format!(
+            "public, max-age={}, stale-while-revalidate={}",
+            config.redis_ttl_secs, config.stale_ttl_secs
+        );
+
+        assert_eq!(cache_control(StatusCode::OK), Some(public));
+        assert_eq!(cache_control(StatusCode::NOT_FOUND), None);
+        assert_eq!(cache_control(StatusCode::INTERNAL_SERVER_ERROR), None);
+    }
+
+    #[test]
+    fn rate_limited_and_unavailable_responses_are_never_stored() {
+        let public = [("cache-control", "public, max-age=60")];
+
+        assert_eq!(cache_control(StatusCode::TOO_MANY_REQUESTS).as_deref(), Some("no-store"));
+        assert_eq!(cache_control((StatusCode::SERVICE_UNAVAILABLE, public)).as_deref(), Some("no-store"));
+    }
+
+    #[test]
+    fn routes_keep_their_own_policy() {
+        let no_store = [("cache-control", "no-store")];
+
+        assert_eq!(cache_control((StatusCode::OK, no_store)).as_deref(), Some("no-store"));
+    }
 }
@@ -0,0 +1,34 @@
+use axum::{
+    extract::MatchedPath,
+    middleware::Next,
+    response::Response,
+    http::Request,
+    body::Body,
+};
+use metrics::{counter, histogram};
+use std::time::Instant;
+
+pub async fn metrics_middleware(
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    let start_time = Instant::now();
+
+    // Label by route template, not the raw path, to keep cardinality bounded
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+    let method = request.method().to_string();
+
+    let response = next.run(request).await;
+
+    let status = response.status().as_u16().to_string();
+    counter!("http_requests_total", "method" => method.clone(), "route" => route.clone(), "status" => status)
+        .increment(1);
+    histogram!("http_request_duration_seconds", "method" => method, "route" => route)
+        .record(start_time.elapsed().as_secs_f64());
+
+    response
+}
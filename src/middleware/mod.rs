@@ -1,9 +1,15 @@
 mod request_id;
-mod timestamp_guard;
+mod signature_guard;
 mod process_time;
 mod cache_header;
+mod metrics;
+mod rate_limit;
+mod admin_guard;
 
 pub use request_id::request_id_middleware;
-pub use timestamp_guard::timestamp_guard_middleware;
+pub use signature_guard::signature_guard_middleware;
 pub use process_time::process_time_middleware;
 pub use cache_header::cache_header_middleware;
+pub use metrics::metrics_middleware;
+pub use rate_limit::rate_limit_middleware;
+pub use admin_guard::admin_guard_middleware;
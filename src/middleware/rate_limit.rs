@@ -0,0 +1,152 @@
+use std::{net::{IpAddr, SocketAddr}, sync::Arc};
+
+use axum::{
+    extract::{ConnectInfo, MatchedPath},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    http::{Request, HeaderMap, HeaderValue, StatusCode},
+    body::Body,
+    Extension,
+};
+use metrics::counter;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    config::{AppConfig, RateLimitConfig},
+    error::ApiError,
+    util::{rate_limit, redis_pool::RedisPool, signing::matches_secret},
+};
+
+/// Per-client token bucket shared across replicas through Redis.
+///
+/// Clients are identified by API key when a configured one is sent, otherwise by IP.
+/// Limits are looked up per route template, falling back to the default.
+pub async fn rate_limit_middleware(
+    Extension(config): Extension<Arc<AppConfig>>,
+    Extension(redis_pool): Extension<RedisPool>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    let rate_limit = &config.rate_limit;
+
+    if !rate_limit.enabled {
+        return next.run(request).await;
+    }
+
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| request.uri().path().to_string());
+
+    let (limit, window_secs) = rate_limit
+        .routes
+        .iter()
+        .find(|route_limit| route_limit.route == route)
+        .map(|route_limit| (route_limit.requests, route_limit.window_secs))
+        .unwrap_or((rate_limit.requests, rate_limit.window_secs));
+
+    let client = client_id(rate_limit, request.headers(), addr);
+    let key = format!("ratelimit:{}:{}", route, client);
+
+    let Some(decision) = rate_limit::check(&redis_pool, &key, limit, window_secs).await else {
+        counter!("rate_limit_decisions_total", "route" => route, "outcome" => "unavailable").increment(1);
+        if rate_limit.fail_open {
+            return next.run(request).await;
+        }
+        return ApiError::Custom(StatusCode::SERVICE_UNAVAILABLE, "rate limiter unavailable".to_string())
+            .into_response();
+    };
+
+    let outcome = if decision.allowed { "allowed" } else { "limited" };
+    counter!("rate_limit_decisions_total", "route" => route, "outcome" => outcome).increment(1);
+
+    let mut response = if decision.allowed {
+        next.run(request).await
+    } else {
+        ApiError::RateLimited(decision.retry_after_secs).into_response()
+    };
+
+    let headers = response.headers_mut();
+    headers.insert("ratelimit-limit", HeaderValue::from(decision.limit));
+    headers.insert("ratelimit-remaining", HeaderValue::from(decision.remaining));
+    headers.insert("ratelimit-reset", HeaderValue::from(decision.reset_secs));
+    headers.insert(
+        "ratelimit-policy",
+        HeaderValue::from_str(&format!("{};w={}", decision.limit, window_secs)).unwrap(),
+    );
+
+    response
+}
+
+/// `key:<sha256>` for configured API keys, so raw keys never reach Redis, otherwise `ip:<addr>`.
+///
+/// Unknown keys fall back to the IP: this runs before the signature guard, so a
+/// client inventing a key per request must not get a fresh bucket each time.
+fn client_id(rate_limit: &RateLimitConfig, headers: &HeaderMap, addr: SocketAddr) -> String {
+    let api_key = headers
+        .get(rate_limit.api_key_header.as_str())
+        .and_then(|value| value.to_str().ok());
+    if let Some(api_key) = api_key.filter(|api_key| matches_secret(api_key, &rate_limit.api_keys)) {
+        return format!("key:{}", hex::encode(Sha256::digest(api_key.as_bytes())));
+    }
+
+    // The trusted proxy appends the address it saw, anything left of it is client-supplied
+    let forwarded_ip = rate_limit
+        .trust_forwarded_for
+        .then(|| headers.get("x-forwarded-for"))
+        .flatten()
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.rsplit(',').next())
+        .and_then(|ip| ip.trim().parse::<IpAddr>().ok());
+
+    format!("ip:{}", forwarded_ip.unwrap_or_else(|| addr.ip()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn forwarded(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-forwarded-for", HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    fn trusting_proxy() -> RateLimitConfig {
+        RateLimitConfig { trust_forwarded_for: true, ..Default::default() }
+    }
+
+    #[test]
+    fn spoofed_leftmost_forwarded_for_is_ignored() {
+        let addr = SocketAddr::from(([10, 0, 0, 1], 4000));
+        let first = client_id(&trusting_proxy(), &forwarded("1.1.1.1, 203.0.113.7"), addr);
+        let second = client_id(&trusting_proxy(), &forwarded("2.2.2.2, 203.0.113.7"), addr);
+
+        assert_eq!(first, "ip:203.0.113.7");
+        assert_eq!(second, first);
+    }
+
+    #[test]
+    fn forwarded_for_is_ignored_unless_trusted_or_valid() {
+        let addr = SocketAddr::from(([10, 0, 0, 1], 4000));
+
+        assert_eq!(client_id(&RateLimitConfig::default(), &forwarded("203.0.113.7"), addr), "ip:10.0.0.1");
+        assert_eq!(client_id(&trusting_proxy(), &forwarded("203.0.113.7, garbage"), addr), "ip:10.0.0.1");
+    }
+
+    #[test]
+    fn only_configured_api_keys_get_their_own_bucket() {
+        let addr = SocketAddr::from(([10, 0, 0, 1], 4000));
+        let config = RateLimitConfig { api_keys: vec!["known".to_string()], ..Default::default() };
+        let with_key = |key: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert("x-api-key", HeaderValue::from_str(key).unwrap());
+            client_id(&config, &headers, addr)
+        };
+
+        assert_eq!(with_key("known"), format!("key:{}", hex::encode(Sha256::digest(b"known"))));
+        assert_eq!(with_key("invented"), "ip:10.0.0.1");
+    }
+}
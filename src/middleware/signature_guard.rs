@@ -0,0 +1,233 @@
+use std::{sync::Arc, time::Duration};
+
+use axum::{
+    middleware::Next,
+    response::IntoResponse,
+    http::{Request, Method, StatusCode},
+    body::{self, Body},
+    Extension,
+};
+use chrono::Utc;
+
+use crate::{
+    config::AppConfig,
+    error::ApiError,
+    util::{
+        cache_backend::{BackendError, CacheBackend},
+        signing::{canonical_request, is_valid_nonce, verify},
+    },
+};
+
+/// Verifies `x-signature`, an HMAC-SHA256 over method, path, query, `x-timestamp`,
+/// the body hash and `x-nonce` if present, made with the key named by `x-key-id`
+/// (any configured key if absent). Signed nonces are recorded in Redis and
+/// rejected on reuse, so a captured request cannot be replayed on any instance.
+pub async fn signature_guard_middleware<B: CacheBackend>(
+    Extension(config): Extension<Arc<AppConfig>>,
+    Extension(backend): Extension<B>,
+    request: Request<Body>,
+    next: Next,
+) -> Result<impl IntoResponse, ApiError> {
+    let signing = &config.signing;
+
+    if !signing.enabled || request.method() == Method::OPTIONS {
+        return Ok(next.run(request).await);
+    }
+
+    let timestamp = header(&request, "x-timestamp").ok_or(ApiError::MissingSignature)?;
+    let signature = header(&request, "x-signature").ok_or(ApiError::MissingSignature)?;
+    let key_id = header(&request, "x-key-id");
+    let nonce = header(&request, "x-nonce");
+
+    match &nonce {
+        Some(nonce) if !is_valid_nonce(nonce) => return Err(ApiError::BadRequest),
+        None if signing.require_nonce => {
+            return Err(ApiError::Custom(StatusCode::BAD_REQUEST, "missing x-nonce header".to_string()));
+        }
+        _ => {}
+    }
+
+    let request_timestamp = timestamp
+        .parse::<u64>()
+        .map_err(|_| ApiError::BadRequest)?;
+
+    let current_timestamp = Utc::now().timestamp() as u64;
+    if current_timestamp.abs_diff(request_timestamp) > signing.max_skew_secs {
+        return Err(ApiError::SignatureExpired);
+    }
+
+    let (parts, body) = request.into_parts();
+    let body = body::to_bytes(body, signing.max_body_bytes)
+        .await
+        .map_err(|_| ApiError::Custom(StatusCode::PAYLOAD_TOO_LARGE, "request body too large".to_string()))?;
+
+    let message = canonical_request(
+        parts.method.as_str(),
+        parts.uri.path(),
+        parts.uri.query().unwrap_or(""),
+        &timestamp,
+        &body,
+        nonce.as_deref(),
+    );
+
+    let valid = match key_id {
+        Some(key_id) => signing
+            .keys
+            .get(&key_id.to_lowercase())
+            .is_some_and(|secret| verify(secret, &message, &signature)),
+        None => signing
+            .keys
+            .values()
+            .any(|secret| verify(secret, &message, &signature)),
+    };
+
+    if !valid {
+        return Err(ApiError::InvalidSignature);
+    }
+
+    // Checked only after the signature so unsigned requests cannot burn nonces
+    if let Some(nonce) = &nonce {
+        // A timestamp is accepted up to max_skew_secs either side of now,
+        // so the nonce has to be remembered for the whole window
+        let ttl = signing.max_skew_secs * 2;
+        if !register_nonce(&backend, nonce, ttl).await? {
+            return Err(ApiError::ReplayedRequest);
+        }
+    }
+
+    let request = Request::from_parts(parts, Body::from(body));
+    Ok(next.run(request).await)
+}
+
+fn header(request: &Request<Body>, name: &str) -> Option<String> {
+    request
+        .headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+}
+
+/// Records a nonce with `SET NX`, returning `false` if it was already used
+async fn register_nonce<B: CacheBackend>(backend: &B, nonce: &str, ttl_secs: u64) -> Result<bool, ApiError> {
+    let key = format!("nonce:{}", nonce);
+    match backend.set_nx(&key, b"1", Duration::from_secs(ttl_secs)).await {
+        Ok(registered) => Ok(registered),
+        // Fail closed: without Redis reuse cannot be detected across instances
+        Err(BackendError::Unavailable) => Err(ApiError::Custom(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "replay protection unavailable".to_string(),
+        )),
+        Err(BackendError::Redis(e)) => Err(ApiError::from(e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{routing::post, Router};
+    use tower::Service;
+
+    use super::*;
+    use crate::{config::SigningConfig, util::{cache_backend::MemoryBackend, signing::sign}};
+
+    const SECRET: &str = "0123456789abcdef0123456789abcdef";
+
+    fn app(backend: MemoryBackend) -> Router {
+        let signing = SigningConfig {
+            enabled: true,
+            keys: [("partner".to_string(), SECRET.to_string())].into(),
+            ..Default::default()
+        };
+        let config = AppConfig { signing, ..Default::default() };
+        Router::new()
+            .route("/v1/echo", post(|body: String| async move { body }))
+            .layer(axum::middleware::from_fn(signature_guard_middleware::<MemoryBackend>))
+            .layer(Extension(backend))
+            .layer(Extension(Arc::new(config)))
+    }
+
+    /// Request signed with `SECRET` at `timestamp`, sent with `key_id`
+    fn signed(timestamp: i64, key_id: Option<&str>, nonce: Option<&str>) -> Request<Body> {
+        let timestamp = timestamp.to_string();
+        let message = canonical_request("POST", "/v1/echo", "", &timestamp, b"hello", nonce);
+        let mut request = Request::post("/v1/echo")
+            .header("x-timestamp", &timestamp)
+            .header("x-signature", sign(SECRET, &message));
+        if let Some(key_id) = key_id {
+            request = request.header("x-key-id", key_id);
+        }
+        if let Some(nonce) = nonce {
+            request = request.header("x-nonce", nonce);
+        }
+        request.body(Body::from("hello")).unwrap()
+    }
+
+    async fn status(app: &mut Router, request: Request<Body>) -> StatusCode {
+        app.call(request).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn signed_requests_pass_with_their_body() {
+        let mut app = app(MemoryBackend::new());
+
+        let response = app.call(signed(Utc::now().timestamp(), Some("partner"), None)).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(&body[..], b"hello");
+    }
+
+    #[tokio::test]
+    async fn key_ids_are_looked_up_case_insensitively() {
+        let mut app = app(MemoryBackend::new());
+        let now = Utc::now().timestamp();
+
+        assert_eq!(status(&mut app, signed(now, Some("Partner"), None)).await, StatusCode::OK);
+        assert_eq!(status(&mut app, signed(now, Some("PARTNER"), None)).await, StatusCode::OK);
+        assert_eq!(status(&mut app, signed(now, None, None)).await, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn unknown_key_ids_are_rejected() {
+        let mut app = app(MemoryBackend::new());
+
+        let status = status(&mut app, signed(Utc::now().timestamp(), Some("someone"), None)).await;
+
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn expired_timestamps_are_rejected() {
+        let mut app = app(MemoryBackend::new());
+        let max_skew = SigningConfig::default().max_skew_secs as i64;
+
+        let past = status(&mut app, signed(Utc::now().timestamp() - max_skew - 5, Some("partner"), None)).await;
+        let future = status(&mut app, signed(Utc::now().timestamp() + max_skew + 5, Some("partner"), None)).await;
+
+        assert_eq!(past, StatusCode::UNAUTHORIZED);
+        assert_eq!(future, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn replayed_nonces_are_rejected_on_every_instance() {
+        let backend = MemoryBackend::new();
+        let (mut first, mut second) = (app(backend.clone()), app(backend));
+        let now = Utc::now().timestamp();
+        let nonce = Some("0123456789abcdef");
+
+        assert_eq!(status(&mut first, signed(now, Some("partner"), nonce)).await, StatusCode::OK);
+        assert_eq!(status(&mut first, signed(now, Some("partner"), nonce)).await, StatusCode::CONFLICT);
+        assert_eq!(status(&mut second, signed(now, Some("partner"), nonce)).await, StatusCode::CONFLICT);
+        let other = Some("fedcba9876543210");
+        assert_eq!(status(&mut second, signed(now, Some("partner"), other)).await, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn tampered_bodies_are_rejected() {
+        let mut app = app(MemoryBackend::new());
+        let (parts, _) = signed(Utc::now().timestamp(), Some("partner"), None).into_parts();
+
+        let status = status(&mut app, Request::from_parts(parts, Body::from("hello!"))).await;
+
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+}
@@ -1,3 +1,3 @@
 mod user;
 
-pub use user::User;
+pub use user::{User, UserKey, UsersKey};
@@ -1,5 +1,7 @@
 use serde::{Deserialize, Serialize};
 
+use crate::util::cache_key::CacheKey;
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Geo {
     pub lat: String,
@@ -34,3 +36,29 @@ pub struct User {
     pub website: String,
     pub company: Company,
 }
+
+/// Cache key of the full user list
+pub struct UsersKey;
+
+impl CacheKey for UsersKey {
+    type Value = Vec<User>;
+    const NAMESPACE: &'static str = "users";
+    const VERSION: u32 = 1;
+
+    fn id(&self) -> String {
+        "all".to_string()
+    }
+}
+
+/// Cache key of a single user
+pub struct UserKey(pub i32);
+
+impl CacheKey for UserKey {
+    type Value = User;
+    const NAMESPACE: &'static str = "user";
+    const VERSION: u32 = 1;
+
+    fn id(&self) -> String {
+        self.0.to_string()
+    }
+}
@@ -26,9 +26,19 @@ impl<T: Serialize> ApiResponse<T> {
             code: None,
         }
     }
+
+    pub fn error_with_data(data: T, message: &str, code: StatusCode) -> Self {
+        Self {
+            status: "error".to_string(),
+            message: message.to_string(),
+            data: ApiData::Data(data),
+            code: Some(code.as_u16()),
+        }
+    }
 }
 
 impl<T> ApiResponse<T> {
+    #[allow(dead_code)]
     pub fn message_only(message: &str) -> Self {
         Self {
             status: "success".to_string(),
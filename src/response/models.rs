@@ -1,6 +1,7 @@
 use serde::Serialize;
 use crate::model::User;
 
+#[allow(dead_code)]
 #[derive(Serialize)]
 pub struct UserResponseData {
     pub user: User,
@@ -8,46 +8,78 @@ use tower::ServiceBuilder;
 
 use crate::{
     handler::{
-        health_checker_handler,
+        cache_keys_handler_get,
+        cache_keys_handler_delete,
+        cache_key_handler_get,
+        cache_key_handler_delete,
+        cache_stats_handler_get,
+        health_live_handler,
+        health_ready_handler,
+        metrics_handler,
         users_handler_get,
         user_id_handler_get,
     },
     error::ApiError,
+    util::redis_pool::RedisPool,
 };
 
-#[allow(warnings, unused)]
-use crate::middleware::timestamp_guard_middleware;
+use crate::middleware::{admin_guard_middleware, rate_limit_middleware, signature_guard_middleware};
 
 pub fn create_router() -> Router {
     // Routes without middleware
     let public_routes = Router::new()
-        .route("/health", get(health_checker_handler));
+        .route("/health", get(health_ready_handler))
+        .route("/health/live", get(health_live_handler))
+        .route("/health/ready", get(health_ready_handler))
+        .route("/metrics", get(metrics_handler));
     
-    let protected_middlewares = ServiceBuilder::new();
+    let protected_middlewares = ServiceBuilder::new()
+        .layer(axum::middleware::from_fn(rate_limit_middleware))
+        .layer(axum::middleware::from_fn(signature_guard_middleware::<RedisPool>))
+        .into_inner();
 
-    #[cfg(not(debug_assertions))]
-    let protected_middlewares = protected_middlewares
-        .layer(axum::middleware::from_fn(timestamp_guard_middleware));
-
-    let protected_middlewares = protected_middlewares.into_inner();
+    // Admin routes additionally need an admin token
+    let admin_middlewares = ServiceBuilder::new()
+        .layer(axum::middleware::from_fn(rate_limit_middleware))
+        .layer(axum::middleware::from_fn(signature_guard_middleware::<RedisPool>))
+        .layer(axum::middleware::from_fn(admin_guard_middleware))
+        .into_inner();
 
     // Routes with middleware
     let protected_routes = Router::new()
         .route(
             "/v1/users",
-            get(users_handler_get)
+            get(users_handler_get::<RedisPool>)
         )
         .route(
             "/v1/user/{id}",
-            get(user_id_handler_get)
+            get(user_id_handler_get::<RedisPool>)
         )
         .layer(
             protected_middlewares
         );
 
+    let admin_routes = Router::new()
+        .route(
+            "/v1/admin/cache/keys",
+            get(cache_keys_handler_get).delete(cache_keys_handler_delete)
+        )
+        .route(
+            "/v1/admin/cache/keys/{key}",
+            get(cache_key_handler_get).delete(cache_key_handler_delete)
+        )
+        .route(
+            "/v1/admin/cache/stats",
+            get(cache_stats_handler_get)
+        )
+        .layer(
+            admin_middlewares
+        );
+
     // Merge routes and add shared state and fallback
     Router::new()
         .merge(public_routes)
         .merge(protected_routes)
+        .merge(admin_routes)
         .fallback(|| async { ApiError::NotFound("not found".to_string()).into_response() })
 }
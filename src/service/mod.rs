@@ -1,2 +1,2 @@
-/* for example */
-// pub(crate) mod llm;
+pub mod user;
+pub mod warmup;
@@ -0,0 +1,33 @@
+use reqwest::{Client, Error as ReqwestError};
+
+use crate::{
+    model::User,
+    util::{
+        cache::{Fetched, JsonResponseExt, Validators},
+        http::RequestBuilderExt,
+        retry::RetryPolicy,
+    },
+};
+
+/// Host of the JSONPlaceholder API, the key of its circuit breaker
+pub const UPSTREAM_HOST: &str = "jsonplaceholder.typicode.com";
+
+/// Fetches all users from JSONPlaceholder, conditionally on the `validators` of the cached list
+pub async fn fetch_users(client: Client, retry: RetryPolicy, validators: Validators) -> Result<Fetched<Vec<User>>, ReqwestError> {
+    client.get(format!("https://{}/users", UPSTREAM_HOST))
+        .conditional(&validators)
+        .send_with_retry(&retry)
+        .await?
+        .json_cached::<Vec<User>>()
+        .await
+}
+
+/// Fetches a single user from JSONPlaceholder, conditionally on the `validators` of the cached one
+pub async fn fetch_user(client: Client, retry: RetryPolicy, id: i32, validators: Validators) -> Result<Fetched<User>, ReqwestError> {
+    client.get(format!("https://{}/users/{}", UPSTREAM_HOST, id))
+        .conditional(&validators)
+        .send_with_retry(&retry)
+        .await?
+        .json_cached::<User>()
+        .await
+}
@@ -0,0 +1,153 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use futures_util::{stream, StreamExt};
+use metrics::counter;
+use tokio::time::MissedTickBehavior;
+use tracing::{info, warn};
+
+use crate::{
+    config::WarmKey,
+    model::{UserKey, UsersKey},
+    service::user::{fetch_user, fetch_users, UPSTREAM_HOST},
+    util::{
+        cache::CacheError, cache_backend::CacheBackend, cache_context::CacheContext,
+        redis_pool::RedisPool, shutdown::Shutdown,
+    },
+};
+
+/// Loads configured keys at startup and keeps hot keys fresh in the background
+#[derive(Clone)]
+pub struct CacheWarmer<B = RedisPool> {
+    cache: CacheContext<B>,
+    warmed: Arc<AtomicBool>,
+}
+
+impl<B: CacheBackend> CacheWarmer<B> {
+    pub fn new(cache: CacheContext<B>) -> Self {
+        let warmed = Arc::new(AtomicBool::new(!cache.config().cache.warmup.enabled));
+        Self { cache, warmed }
+    }
+
+    /// Whether the startup warm-up has finished, or timed out
+    pub fn is_warmed(&self) -> bool {
+        self.warmed.load(Ordering::Relaxed)
+    }
+
+    /// Loads the warm-up keys, from Redis when present, then marks the instance warmed.
+    ///
+    /// Failed keys are logged and skipped, they are fetched on first request as usual.
+    pub async fn warm_up(&self) {
+        let warmup = &self.cache.config().cache.warmup;
+        if !warmup.enabled {
+            return;
+        }
+
+        let started = Instant::now();
+        let keys = parse_keys(&warmup.keys);
+        let count = keys.len();
+        let warm_all = stream::iter(keys)
+            .for_each_concurrent(warmup.concurrency, |key| async move {
+                let outcome = match self.warm(key).await {
+                    Ok(()) => "loaded",
+                    Err(CacheError::NotFound) => "not_found",
+                    Err(e) if e.is_circuit_open() => "circuit_open",
+                    Err(e) => {
+                        warn!("Cache warm-up of '{}' failed: {:?}", key, e);
+                        "error"
+                    }
+                };
+                counter!("cache_warmup_keys_total", "outcome" => outcome).increment(1);
+            });
+
+        match tokio::time::timeout(Duration::from_secs(warmup.timeout_secs), warm_all).await {
+            Ok(()) => info!("Cache warm-up of {} keys finished in {}ms", count, started.elapsed().as_millis()),
+            Err(_) => warn!("Cache warm-up did not finish within {}s, reporting ready anyway", warmup.timeout_secs),
+        }
+        self.warmed.store(true, Ordering::Relaxed);
+    }
+
+    /// Spawns a task that refetches hot keys once they turn stale within `refresh_ahead_secs`.
+    /// A pass in progress finishes before the task stops.
+    pub fn spawn_hot_refresh(&self, shutdown: &Shutdown) {
+        let hot_keys = &self.cache.config().cache.hot_keys;
+        if !hot_keys.enabled || hot_keys.keys.is_empty() {
+            return;
+        }
+
+        let warmer = self.clone();
+        let keys = parse_keys(&hot_keys.keys);
+        let interval = Duration::from_secs(hot_keys.interval_secs);
+        let ahead = Duration::from_secs(hot_keys.refresh_ahead_secs);
+        let stop = shutdown.clone();
+        shutdown.spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            loop {
+                tokio::select! {
+                    _ = ticker.tick() => {}
+                    _ = stop.stopping() => return,
+                }
+                stream::iter(keys.iter().copied())
+                    .for_each_concurrent(None, |key| {
+                        let warmer = &warmer;
+                        async move {
+                            let outcome = match warmer.refresh(key, ahead).await {
+                                Ok(true) => "refreshed",
+                                Ok(false) => "fresh",
+                                Err(e) if e.is_circuit_open() => "circuit_open",
+                                Err(e) => {
+                                    warn!("Scheduled refresh of '{}' failed: {:?}", key, e);
+                                    "error"
+                                }
+                            };
+                            counter!("cache_hot_refreshes_total", "outcome" => outcome).increment(1);
+                        }
+                    })
+                    .await;
+            }
+        });
+    }
+
+    async fn warm(&self, key: WarmKey) -> Result<(), CacheError> {
+        let retry = self.cache.retry().clone();
+        match key {
+            WarmKey::Users => self
+                .cache.wrapper::<UsersKey>(UPSTREAM_HOST)
+                .get_or_fetch(&UsersKey, move |client, validators| fetch_users(client, retry, validators))
+                .await
+                .map(drop),
+            WarmKey::User(id) => self
+                .cache.wrapper::<UserKey>(UPSTREAM_HOST)
+                .get_or_fetch(&UserKey(id), move |client, validators| fetch_user(client, retry, id, validators))
+                .await
+                .map(drop),
+        }
+    }
+
+    async fn refresh(&self, key: WarmKey, ahead: Duration) -> Result<bool, CacheError> {
+        let retry = self.cache.retry().clone();
+        match key {
+            WarmKey::Users => {
+                self.cache.wrapper::<UsersKey>(UPSTREAM_HOST)
+                    .refresh_ahead(&UsersKey, ahead, move |client, validators| fetch_users(client, retry, validators))
+                    .await
+            }
+            WarmKey::User(id) => {
+                self.cache.wrapper::<UserKey>(UPSTREAM_HOST)
+                    .refresh_ahead(&UserKey(id), ahead, move |client, validators| fetch_user(client, retry, id, validators))
+                    .await
+            }
+        }
+    }
+}
+
+/// Parses configured keys, already checked by config validation
+fn parse_keys(keys: &[String]) -> Vec<WarmKey> {
+    keys.iter().filter_map(|key| WarmKey::parse(key)).collect()
+}
@@ -0,0 +1,33 @@
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+/// Static build metadata plus the process start time
+#[derive(Clone, Serialize)]
+pub struct BuildInfo {
+    pub version: &'static str,
+    pub git_sha: &'static str,
+    #[serde(skip)]
+    started_at: Instant,
+}
+
+impl BuildInfo {
+    pub fn new() -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            git_sha: env!("GIT_SHA"),
+            started_at: Instant::now(),
+        }
+    }
+
+    /// Time since the process started
+    pub fn uptime(&self) -> Duration {
+        self.started_at.elapsed()
+    }
+}
+
+impl Default for BuildInfo {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,26 +1,65 @@
 use bb8_redis::{
-    bb8::{Pool, RunError},
-    RedisConnectionManager,
-    redis::{RedisError, AsyncCommands},
+    bb8::RunError,
+    redis::RedisError,
 };
 
-use serde::{de::DeserializeOwned, Serialize};
-use serde_json::{to_string, from_str};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
-use moka::future::Cache;
-use reqwest::{Client, Error as ReqwestError};
+use chrono::Utc;
+use metrics::counter;
+use moka::{future::Cache, Expiry};
+use reqwest::{header::{HeaderMap, ETAG, LAST_MODIFIED}, Client, Error as ReqwestError, StatusCode};
 
+use std::any::Any;
 use std::time::Duration;
 use std::future::Future;
+use std::sync::Arc;
+
+use tracing::{debug, warn};
+
+use tokio::time::Instant;
+use tokio_util::task::TaskTracker;
+
+use crate::config::{CacheConfig, CompressionConfig, RefreshLockConfig};
+use crate::util::{
+    cache_backend::{BackendError, CacheBackend},
+    cache_key::CacheKey,
+    circuit_breaker::{is_upstream_failure, CircuitBreaker, CircuitBreakers},
+    codec::{self, CodecError, CodecKind},
+    compression,
+    invalidation::{Invalidation, Invalidator},
+    metrics::record_cache_lookup,
+    redis_pool::RedisPool,
+    refresh_lock::Acquire,
+    single_flight::SingleFlight,
+};
 
 #[derive(Debug)]
 pub enum CacheError {
     Redis(RunError<RedisError>),      // Error related to Redis connection or operations
     Reqwest(ReqwestError),            // Error related to HTTP requests
     Serialization(serde_json::Error), // Error related to JSON serialization/deserialization
+    Codec(CodecError),                // Error encoding or decoding a cached value
     NotFound,                         // Error indicating that the data was not found
+    CircuitOpen,                      // The upstream host keeps failing, the fetch was not attempted
+    UnexpectedNotModified,            // The upstream answered a fetch without validators with 304
+    Shared(Arc<CacheError>),          // Error from a coalesced fetch, shared by all its waiters
 }
 
+impl CacheError {
+    /// Whether the fetch was rejected by an open circuit, shared errors included
+    pub fn is_circuit_open(&self) -> bool {
+        match self {
+            CacheError::CircuitOpen => true,
+            CacheError::Shared(e) => e.is_circuit_open(),
+            _ => false,
+        }
+    }
+}
+
+/// Result of a coalesced upstream fetch, shared with the waiters without re-parsing
+pub type CacheFlights = SingleFlight<Result<Stored<dyn Any + Send + Sync>, Arc<CacheError>>>;
+
 // Implement conversion from Redis errors to CacheError
 impl From<RunError<RedisError>> for CacheError {
     fn from(err: RunError<RedisError>) -> Self {
@@ -42,35 +81,310 @@ impl From<serde_json::Error> for CacheError {
     }
 }
 
-pub struct CacheWrapper<T> {
-    redis_pool: Pool<RedisConnectionManager>,   // Redis connection pool
-    moka_cache: Cache<String, String>,          // Moka in-memory cache
-    cache_ttl: Duration,                        // Time-to-live for Redis cache
+// Implement conversion from codec errors to CacheError
+impl From<CodecError> for CacheError {
+    fn from(err: CodecError) -> Self {
+        CacheError::Codec(err)
+    }
+}
+
+/// Marker stored in place of a value when the upstream reported "not found"
+pub const NOT_FOUND_MARKER: &[u8] = b"__not_found__";
+
+/// How long a stale entry is treated as fresh on this instance while it is being refreshed
+const REFRESH_GRACE: Duration = Duration::from_secs(5);
+
+/// Upstream validators of a cached value, sent back to refetch it conditionally
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Validators {
+    pub etag: Option<String>,          // Sent back as `If-None-Match`
+    pub last_modified: Option<String>, // Sent back as `If-Modified-Since`
+}
+
+impl Validators {
+    /// Validators of an upstream response
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let header = |name| headers.get(name).and_then(|value| value.to_str().ok()).map(str::to_string);
+        Self {
+            etag: header(ETAG),
+            last_modified: header(LAST_MODIFIED),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.etag.is_none() && self.last_modified.is_none()
+    }
+}
+
+/// Outcome of an upstream fetch
+pub enum Fetched<V> {
+    Found { data: V, validators: Validators },
+    NotFound,
+    NotModified, // 304 to a conditional fetch, the cached value is still current
+}
+
+/// Decoded value with its soft expiry, kept in Moka so a hit needs no deserialization
+pub enum Stored<V: ?Sized> {
+    Found {
+        data: Arc<V>,
+        fresh_until: i64,
+        validators: Option<Arc<Validators>>,
+    },
+    NotFound,
+}
+
+impl<V: ?Sized> Clone for Stored<V> {
+    fn clone(&self) -> Self {
+        match self {
+            Stored::Found { data, fresh_until, validators } => Stored::Found {
+                data: data.clone(),
+                fresh_until: *fresh_until,
+                validators: validators.clone(),
+            },
+            Stored::NotFound => Stored::NotFound,
+        }
+    }
+}
+
+impl<V: Any + Send + Sync> Stored<V> {
+    /// Erases the value type so entries of every wrapper fit in the shared Moka cache
+    fn erase(self) -> Stored<dyn Any + Send + Sync> {
+        match self {
+            Stored::Found { data, fresh_until, validators } => Stored::Found { data, fresh_until, validators },
+            Stored::NotFound => Stored::NotFound,
+        }
+    }
+}
+
+impl Stored<dyn Any + Send + Sync> {
+    /// Recovers the value type; `None` if the entry holds a different type
+    fn downcast<V: Any + Send + Sync>(self) -> Option<Stored<V>> {
+        match self {
+            Stored::Found { data, fresh_until, validators } => data
+                .downcast::<V>()
+                .ok()
+                .map(|data| Stored::Found { data, fresh_until, validators }),
+            Stored::NotFound => Some(Stored::NotFound),
+        }
+    }
+}
+
+impl<V: ?Sized> Stored<V> {
+    /// Classifies the value as fresh, stale or "not found"
+    fn lookup(self) -> Lookup<Arc<V>> {
+        match self {
+            Stored::Found { data, fresh_until, .. } if fresh_until > Utc::now().timestamp_millis() => Lookup::Fresh(data),
+            Stored::Found { data, .. } => Lookup::Stale(data),
+            Stored::NotFound => Lookup::NotFound,
+        }
+    }
+
+    /// The value with its validators, if it can be refetched conditionally
+    fn revalidation(&self) -> Option<Revalidation<V>> {
+        match self {
+            Stored::Found { data, validators: Some(validators), .. } => Some(Revalidation {
+                data: data.clone(),
+                validators: validators.clone(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Cached value to keep if the upstream answers a conditional fetch with 304
+struct Revalidation<V: ?Sized> {
+    data: Arc<V>,
+    validators: Arc<Validators>,
+}
+
+/// Entry of the Moka tier; each one expires after its own TTL
+#[derive(Clone)]
+pub struct MemoryEntry {
+    value: Stored<dyn Any + Send + Sync>,
+    ttl: Duration,
+    expires_at: Instant,
+}
+
+impl MemoryEntry {
+    /// TTL the entry was inserted with
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    /// Time left before the entry expires
+    fn remaining(&self) -> Duration {
+        self.expires_at.saturating_duration_since(Instant::now())
+    }
+
+    /// Soft expiry in Unix milliseconds, `None` for a "not found" marker
+    pub fn fresh_until(&self) -> Option<i64> {
+        match self.value {
+            Stored::Found { fresh_until, .. } => Some(fresh_until),
+            Stored::NotFound => None,
+        }
+    }
+}
+
+/// Moka tier shared by all wrappers
+pub type MemoryCache = Cache<String, MemoryEntry>;
+
+/// Expires Moka entries after the TTL they were inserted with
+pub struct MemoryExpiry;
+
+impl Expiry<String, MemoryEntry> for MemoryExpiry {
+    fn expire_after_create(
+        &self,
+        _key: &String,
+        entry: &MemoryEntry,
+        _created_at: std::time::Instant,
+    ) -> Option<Duration> {
+        Some(entry.ttl)
+    }
+
+    fn expire_after_update(
+        &self,
+        _key: &String,
+        entry: &MemoryEntry,
+        _updated_at: std::time::Instant,
+        _duration_until_expiry: Option<Duration>,
+    ) -> Option<Duration> {
+        Some(entry.ttl)
+    }
+}
+
+/// TTLs a wrapper applies to each tier, and how it encodes values for Redis
+#[derive(Clone, Debug)]
+pub struct CachePolicy {
+    pub memory_ttl: Duration,             // How long a value is kept in Moka, capped by its Redis TTL
+    pub redis_ttl: Duration,              // How long a value is served as fresh
+    pub stale_ttl: Duration,              // How long past that it may be served stale
+    pub negative_ttl: Duration,           // How long a "not found" result is cached
+    pub jitter: f64,                      // Random fraction added to the Redis TTLs
+    pub codec: CodecKind,                 // Serialization format of new entries
+    pub compression: CompressionConfig,   // Compression of large values in Redis
+}
+
+impl CachePolicy {
+    /// Policy of the wrapper `name`: the `[cache]` TTLs, overridden by `[cache.policies.<name>]`
+    pub fn from_config(config: &CacheConfig, name: &str) -> Self {
+        let overrides = config.policies.get(name).cloned().unwrap_or_default();
+        Self {
+            memory_ttl: Duration::from_secs(overrides.memory_ttl_secs.unwrap_or(config.memory_ttl_secs)),
+            redis_ttl: Duration::from_secs(overrides.redis_ttl_secs.unwrap_or(config.redis_ttl_secs)),
+            stale_ttl: Duration::from_secs(overrides.stale_ttl_secs.unwrap_or(config.stale_ttl_secs)),
+            negative_ttl: Duration::from_secs(overrides.negative_ttl_secs.unwrap_or(config.negative_ttl_secs)),
+            jitter: overrides.ttl_jitter.unwrap_or(config.ttl_jitter),
+            codec: overrides.codec.unwrap_or(config.codec),
+            compression: config.compression.clone(),
+        }
+    }
+
+    /// Extends `ttl` by a random fraction of up to `jitter`
+    fn jittered(&self, ttl: Duration) -> Duration {
+        if self.jitter <= 0.0 {
+            return ttl;
+        }
+        ttl.mul_f64(1.0 + rand::random_range(0.0..=self.jitter))
+    }
+}
+
+/// Value stored in Redis, with its soft expiry.
+///
+/// The hard expiry is the tier TTL; between `fresh_until` and the hard expiry
+/// the value is served stale while a background task refreshes it.
+#[derive(Serialize, Deserialize)]
+struct CacheEntry<D> {
+    fresh_until: i64, // Unix time in milliseconds
+    data: D,
+    #[serde(default)]
+    validators: Option<Validators>, // Absent from entries written before revalidation
+}
+
+/// Outcome of reading a tier
+enum Lookup<T> {
+    Fresh(T),
+    Stale(T),
+    NotFound,
+}
+
+pub struct CacheWrapper<K, B = RedisPool> {
+    backend: B,                                 // Shared tier, Redis unless testing
+    moka_cache: MemoryCache,                    // Moka in-memory cache
+    flights: CacheFlights,                      // In-flight upstream fetches, shared by all wrappers
+    policy: CachePolicy,                        // TTLs of each tier
     http_client: Client,                        // Reqwest HTTP client
-    _phantom: std::marker::PhantomData<T>,      // Marker for generic type T
+    invalidator: Option<Invalidator>,           // Propagates writes and deletes to other instances
+    refresh_lock: Option<RefreshLockConfig>,    // Cross-instance lock taken before fetching upstream
+    circuit_breaker: Option<CircuitBreaker>,    // Fails fetches fast while the upstream host keeps failing
+    tasks: TaskTracker,                         // Background refreshes, awaited on shutdown
+    _phantom: std::marker::PhantomData<fn() -> K>, // Marker for the key type K
+}
+
+impl<K, B: Clone> Clone for CacheWrapper<K, B> {
+    fn clone(&self) -> Self {
+        Self {
+            backend: self.backend.clone(),
+            moka_cache: self.moka_cache.clone(),
+            flights: self.flights.clone(),
+            policy: self.policy.clone(),
+            http_client: self.http_client.clone(),
+            invalidator: self.invalidator.clone(),
+            refresh_lock: self.refresh_lock.clone(),
+            circuit_breaker: self.circuit_breaker.clone(),
+            tasks: self.tasks.clone(),
+            _phantom: std::marker::PhantomData,
+        }
+    }
 }
 
 // A generic wrapper for Redis-based caching
-impl<T> CacheWrapper<T>
-where
-    T: Serialize + DeserializeOwned + Send + Sync,
-{
+impl<K: CacheKey + 'static, B: CacheBackend> CacheWrapper<K, B> {
     /// Constructor for CacheWrapper
     pub fn new(
-        redis_pool: Pool<RedisConnectionManager>,
-        moka_cache: Cache<String, String>,
-        cache_ttl_secs: u64,
+        backend: B,
+        moka_cache: MemoryCache,
+        flights: CacheFlights,
+        policy: CachePolicy,
         http_client: Client,
     ) -> Self {
         Self {
-            redis_pool,
+            backend,
             moka_cache,
-            cache_ttl: Duration::from_secs(cache_ttl_secs),
+            flights,
+            policy,
             http_client,
+            invalidator: None,
+            refresh_lock: None,
+            circuit_breaker: None,
+            tasks: TaskTracker::new(),
             _phantom: std::marker::PhantomData,
         }
     }
 
+    /// Evicts the Moka entry on every other instance whenever a key is set or deleted
+    pub fn with_invalidator(mut self, invalidator: Invalidator) -> Self {
+        self.invalidator = Some(invalidator);
+        self
+    }
+
+    /// Lets only one replica at a time fetch a key from upstream, if enabled in `config`
+    pub fn with_refresh_lock(mut self, config: &RefreshLockConfig) -> Self {
+        self.refresh_lock = config.enabled.then(|| config.clone());
+        self
+    }
+
+    /// Guards fetches with the circuit breaker of the upstream `host`, if breakers are enabled
+    pub fn with_circuit_breaker(mut self, breakers: &CircuitBreakers, host: &str) -> Self {
+        self.circuit_breaker = breakers.for_host(host);
+        self
+    }
+
+    /// Spawns background refreshes on `tasks`, so shutdown can wait for their writes
+    pub fn with_task_tracker(mut self, tasks: TaskTracker) -> Self {
+        self.tasks = tasks;
+        self
+    }
+
     /// Get the HTTP client
     #[allow(dead_code)]
     pub fn client(&self) -> &Client {
@@ -78,120 +392,521 @@ where
     }
 
     /// Attempts to retrieve the value from Moka, Redis, or HTTP (via `http_fetch`).
+    ///
+    /// Stale values are returned immediately and refreshed in the background.
+    /// Moka holds decoded values, so a memory hit only clones an `Arc`. While the
+    /// upstream circuit is open, stale values are kept alive instead of expiring,
+    /// and a miss is served the last known value if Redis still has one.
     pub async fn get_or_fetch<F, Fut>(
         &self,
-        key: &str,
+        key: &K,
         http_fetch: F,
-    ) -> Result<T, CacheError>
+    ) -> Result<Arc<K::Value>, CacheError>
     where
-        F: FnOnce(Client) -> Fut,
-        Fut: Future<Output = Result<Option<T>, ReqwestError>> + Send,
+        F: FnOnce(Client, Validators) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<Fetched<K::Value>, ReqwestError>> + Send + 'static,
     {
+        let key = &key.cache_key();
+
         // Check Moka cache
-        if let Some(cached_value) = self.moka_cache.get(key).await {
-            if cached_value == "__not_found__" {
+        let memory = self.memory_get(key).await;
+        let revalidation = memory.as_ref().and_then(|(stored, _)| stored.revalidation());
+        match memory.map(|(stored, remaining)| (stored.lookup(), remaining)) {
+            Some((Lookup::Fresh(data), _)) => {
+                record_cache_lookup("moka", "hit");
+                return Ok(data);
+            }
+            Some((Lookup::Stale(data), remaining)) => {
+                record_cache_lookup("moka", "stale");
+                self.refresh_in_background(key, data.clone(), remaining, revalidation, http_fetch).await;
+                return Ok(data);
+            }
+            Some((Lookup::NotFound, _)) => {
+                record_cache_lookup("negative", "hit");
                 return Err(CacheError::NotFound);
             }
-            if let Ok(parsed_data) = from_str(&cached_value) {
-                return Ok(parsed_data);
+            None => record_cache_lookup("moka", "miss"),
+        }
+
+        // Check Redis cache, skipped while Redis is degraded
+        if let Some(cached) = self.redis_get(key).await {
+            if let Some((stored, remaining)) = cached {
+                match stored.clone().lookup() {
+                    Lookup::Fresh(data) => {
+                        // Cache the result in Moka, for no longer than it stays in Redis
+                        self.memory_insert(key, stored.erase(), remaining).await;
+                        record_cache_lookup("redis", "hit");
+                        return Ok(data);
+                    }
+                    Lookup::Stale(data) => {
+                        record_cache_lookup("redis", "stale");
+                        self.refresh_in_background(key, data.clone(), remaining, stored.revalidation(), http_fetch).await;
+                        return Ok(data);
+                    }
+                    Lookup::NotFound => {
+                        // Cache "not found" marker in Moka
+                        self.memory_insert(key, Stored::NotFound, remaining).await;
+                        record_cache_lookup("negative", "hit");
+                        return Err(CacheError::NotFound);
+                    }
+                }
             }
+            record_cache_lookup("redis", "miss");
+        } else {
+            record_cache_lookup("redis", "skipped");
         }
 
-        // Check Redis cache
-        let mut conn = self.redis_pool.get().await.map_err(CacheError::from)?;
-        if let Ok(Some(cached_data)) = conn.get::<_, Option<String>>(key).await {
-            if cached_data == "__not_found__" {
-                // Cache "not found" marker in Moka
-                self.moka_cache.insert(key.to_string(), "__not_found__".to_string()).await;
-                return Err(CacheError::NotFound);
+        // Fetch from HTTP request, or fall back to the last known value while the circuit is open
+        match self.fetch_and_store(key, None, http_fetch).await {
+            Err(e) if e.is_circuit_open() => self.last_known(key).await.ok_or(e),
+            result => result,
+        }
+    }
+
+    /// Calls the upstream and stores the result, or a "not found" marker, in both tiers.
+    ///
+    /// Concurrent calls for the same key are coalesced: one caller fetches and
+    /// writes to Redis, the others wait for and share its result. With the refresh
+    /// lock enabled, the same holds across replicas. With a `revalidation`, the
+    /// fetch is conditional and a 304 keeps the cached value.
+    async fn fetch_and_store<F, Fut>(
+        &self,
+        key: &str,
+        revalidation: Option<Revalidation<K::Value>>,
+        http_fetch: F,
+    ) -> Result<Arc<K::Value>, CacheError>
+    where
+        F: FnOnce(Client, Validators) -> Fut,
+        Fut: Future<Output = Result<Fetched<K::Value>, ReqwestError>>,
+    {
+        let (shared, coalesced) = self.flights.run(key, || async {
+            // Checked before the refresh lock, so an open circuit costs no Redis round trips
+            if self.circuit_breaker.as_ref().is_some_and(|breaker| !breaker.try_acquire()) {
+                return Err(Arc::new(CacheError::CircuitOpen));
             }
-            if let Ok(parsed_data) = from_str(&cached_data) {
-                // Cache the result in Moka
-                self.moka_cache.insert(key.to_string(), cached_data).await;
-                return Ok(parsed_data);
+
+            let token = match &self.refresh_lock {
+                Some(lock) => {
+                    let ttl = Duration::from_millis(lock.lock_ttl_ms);
+                    match self.backend.acquire_lock(key, ttl).await {
+                        Acquire::Acquired(token) => Some(token),
+                        Acquire::Held => {
+                            if let Some(stored) = self.wait_for_lock_holder(key, lock).await {
+                                return Ok(stored.erase());
+                            }
+                            None
+                        }
+                        Acquire::Unavailable => None,
+                    }
+                }
+                None => None,
+            };
+
+            let result = self.fetch_upstream(key, revalidation, http_fetch, token).await;
+            if let Some(token) = token {
+                self.backend.release_lock(key, token).await;
             }
+            result.map(Stored::erase)
+        }).await;
+
+        if coalesced {
+            counter!("cache_coalesced_requests_total").increment(1);
         }
 
-        // Fetch from HTTP request
+        match shared {
+            Ok(stored) => match stored.downcast::<K::Value>() {
+                Some(Stored::Found { data, .. }) => Ok(data),
+                Some(Stored::NotFound) => Err(CacheError::NotFound),
+                // Only possible if two key types share a namespace
+                None => {
+                    warn!("In-flight fetch of '{}' returned a value of another type", key);
+                    Err(CacheError::NotFound)
+                }
+            },
+            // Only the last holder gets the original error back, everyone else shares it
+            Err(e) => Err(Arc::try_unwrap(e).unwrap_or_else(CacheError::Shared)),
+        }
+    }
+
+    /// Calls the upstream and stores the result, or a "not found" marker, in both tiers.
+    ///
+    /// With a fencing token, Redis is only written while this replica still holds the refresh lock.
+    async fn fetch_upstream<F, Fut>(
+        &self,
+        key: &str,
+        revalidation: Option<Revalidation<K::Value>>,
+        http_fetch: F,
+        token: Option<u64>,
+    ) -> Result<Stored<K::Value>, Arc<CacheError>>
+    where
+        F: FnOnce(Client, Validators) -> Fut,
+        Fut: Future<Output = Result<Fetched<K::Value>, ReqwestError>>,
+    {
         // Use clone of the client to avoid lifetime issues
         let client_clone = self.http_client.clone();
-        let http_result = http_fetch(client_clone).await.map_err(CacheError::from)?;
+        let validators = revalidation.as_ref().map(|r| (*r.validators).clone()).unwrap_or_default();
+        let result = http_fetch(client_clone, validators).await;
+        if let Some(breaker) = &self.circuit_breaker {
+            match &result {
+                Err(e) if is_upstream_failure(e) => breaker.record_failure(),
+                _ => breaker.record_success(),
+            }
+        }
 
-        if let Some(data) = http_result {
-            // Cache the result in both Moka and Redis
-            if let Ok(serialized) = to_string(&data) {
-                self.moka_cache.insert(key.to_string(), serialized.clone()).await;
-                let mut conn = self.redis_pool.get().await.map_err(CacheError::from)?;
-                let _: Result<(), _> = conn.set_ex(key, serialized, self.cache_ttl.as_secs()).await;
+        match result {
+            Ok(Fetched::Found { data, validators }) => {
+                record_cache_lookup("upstream", "found");
+                // Cache the result in both Moka and Redis
+                let validators = (!validators.is_empty()).then(|| Arc::new(validators));
+                let stored = self.write_found(key, Arc::new(data), validators, token).await.map_err(Arc::new)?;
+                Ok(stored)
+            }
+            Ok(Fetched::NotModified) => {
+                let Some(Revalidation { data, validators }) = revalidation else {
+                    // Only a conditional fetch can be answered with 304
+                    warn!("Upstream answered an unconditional fetch of '{}' with 304", key);
+                    record_cache_lookup("upstream", "error");
+                    return Err(Arc::new(CacheError::UnexpectedNotModified));
+                };
+                record_cache_lookup("upstream", "not_modified");
+                // Keep the cached value, fresh for another Redis TTL
+                let stored = self.write_found(key, data, Some(validators), token).await.map_err(Arc::new)?;
+                Ok(stored)
+            }
+            Ok(Fetched::NotFound) => {
+                // Cache "not found" marker in both Moka and Redis
+                record_cache_lookup("upstream", "not_found");
+                self.write_not_found(key, token).await;
+                Ok(Stored::NotFound)
+            }
+            Err(e) => {
+                record_cache_lookup("upstream", "error");
+                Err(Arc::new(CacheError::from(e)))
+            }
+        }
+    }
+
+    /// Polls Redis while another replica holds the refresh lock, until it has stored
+    /// a fresh value or "not found". Returns `None` on timeout or if Redis fails.
+    async fn wait_for_lock_holder(&self, key: &str, lock: &RefreshLockConfig) -> Option<Stored<K::Value>> {
+        let deadline = Instant::now() + Duration::from_millis(lock.wait_timeout_ms);
+        let poll_interval = Duration::from_millis(lock.poll_interval_ms);
+
+        while Instant::now() < deadline {
+            tokio::time::sleep(poll_interval).await;
+
+            let Some((stored, remaining)) = self.redis_get(key).await? else {
+                continue;
+            };
+
+            if let Lookup::Fresh(_) | Lookup::NotFound = stored.clone().lookup() {
+                self.memory_insert(key, stored.clone().erase(), remaining).await;
+                counter!("cache_refresh_lock_waits_total", "outcome" => "filled").increment(1);
+                return Some(stored);
             }
-            Ok(data)
-        } else {
-            // Cache "not found" marker in both Moka and Redis
-            self.cache_not_found(key).await?;
-            Err(CacheError::NotFound)
         }
+
+        counter!("cache_refresh_lock_waits_total", "outcome" => "timed_out").increment(1);
+        None
+    }
+
+    /// Refreshes a stale key without blocking the caller.
+    ///
+    /// The stale value is first re-inserted into Moka as fresh for a short grace
+    /// period, so concurrent requests on this instance don't start their own refresh.
+    /// The grace never outlasts the `remaining` lifetime of the value in Redis.
+    async fn refresh_in_background<F, Fut>(
+        &self,
+        key: &str,
+        stale: Arc<K::Value>,
+        remaining: Duration,
+        revalidation: Option<Revalidation<K::Value>>,
+        http_fetch: F,
+    )
+    where
+        F: FnOnce(Client, Validators) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<Fetched<K::Value>, ReqwestError>> + Send + 'static,
+    {
+        let now = Utc::now().timestamp_millis();
+        let validators = revalidation.as_ref().map(|r| r.validators.clone());
+        let grace = Stored::Found {
+            data: stale.clone(),
+            fresh_until: now + REFRESH_GRACE.as_millis() as i64,
+            validators: validators.clone(),
+        };
+        self.memory_insert(key, grace.erase(), REFRESH_GRACE.min(remaining)).await;
+
+        let cache = self.clone();
+        let key = key.to_string();
+        self.tasks.spawn(async move {
+            let outcome = match cache.fetch_and_store(&key, revalidation, http_fetch).await {
+                Ok(_) => "refreshed",
+                Err(CacheError::NotFound) => "not_found",
+                Err(e) if e.is_circuit_open() => {
+                    let stale = Stored::Found { data: stale, fresh_until: now, validators };
+                    cache.keep_stale(&key, stale).await;
+                    "circuit_open"
+                }
+                Err(e) => {
+                    warn!("Background refresh of '{}' failed, keeping stale value: {:?}", key, e);
+                    "error"
+                }
+            };
+            counter!("cache_refreshes_total", "outcome" => outcome).increment(1);
+        });
+    }
+
+    /// Keeps a stale value in both tiers for another stale TTL while the circuit is
+    /// open, rather than letting it expire into misses. Each rejected refresh extends
+    /// it again; a value that expires anyway is served from its last known copy.
+    async fn keep_stale(&self, key: &str, stale: Stored<K::Value>) {
+        let ttl = self.policy.stale_ttl;
+        if let Ok(true) = self.backend.extend_ttl(key, ttl).await {
+            counter!("cache_stale_extensions_total", "namespace" => K::NAMESPACE).increment(1);
+        }
+        let remaining = match self.backend.pttl(key).await {
+            Ok(Some(remaining)) => remaining,
+            // Gone from Redis, the next miss reads the last known copy instead
+            Ok(None) => return,
+            // Redis is down too, so Moka holds the only copy
+            Err(_) => ttl,
+        };
+        self.memory_insert(key, stale.erase(), remaining).await;
+    }
+
+    /// Reads the long-lived copy of a key that has expired from Redis, served while the
+    /// circuit is open. It is kept in Moka as stale, so its refreshes keep it there.
+    async fn last_known(&self, key: &str) -> Option<Arc<K::Value>> {
+        let (stored, remaining) = self.redis_get(&last_known_key(key)).await??;
+        let Stored::Found { data, .. } = &stored else {
+            return None;
+        };
+        let data = data.clone();
+        self.memory_insert(key, stored.erase(), remaining).await;
+        record_cache_lookup("last_known", "hit");
+        Some(data)
     }
 
     /// Caches a "not found" marker in both Moka and Redis
-    pub async fn cache_not_found(&self, key: &str) -> Result<(), CacheError> {
-        self.moka_cache.insert(key.to_string(), "__not_found__".to_string()).await;
-        let mut conn = self.redis_pool.get().await.map_err(CacheError::from)?;
-        let _: Result<(), _> = conn
-            .set_ex(key, "__not_found__", self.cache_ttl.as_secs())
-            .await;
+    #[allow(dead_code)]
+    pub async fn cache_not_found(&self, key: &K) -> Result<(), CacheError> {
+        self.write_not_found(&key.cache_key(), None).await;
         Ok(())
     }
 
     /// Updates the cache with new data for a given key in both Moka and Redis
     #[allow(dead_code)]
-    pub async fn set(&self, key: &str, data: &T) -> Result<(), CacheError> {
-        let serialized = to_string(data).map_err(|e| CacheError::Serialization(e))?;
-
-        // Check Moka cache first
-        if let Some(cached_value) = self.moka_cache.get(key).await {
-            // If cached data is the same as the new data, skip deletion
-            if cached_value == serialized {
-                return Ok(());
-            }
-        }
-
-        // Update Moka cache
-        self.moka_cache.insert(key.to_string(), serialized.clone()).await;
+    pub async fn set(&self, key: &K, data: K::Value) -> Result<(), CacheError> {
+        let key = &key.cache_key();
 
-        // Update Redis cache
-        let mut conn = self.redis_pool.get().await.map_err(CacheError::from)?;
-        let _: Result<(), _> = conn
-            .set_ex(key, serialized, self.cache_ttl.as_secs())
-            .await;
+        // Update both Moka and Redis caches
+        self.write_found(key, Arc::new(data), None, None).await?;
 
+        self.publish_invalidation(key).await;
         Ok(())
     }
 
     /// Deletes a key from both Moka and Redis
     #[allow(dead_code)]
-    pub async fn delete(&self, key: &str) -> Result<(), CacheError> {
-        self.moka_cache.invalidate(key).await;
-        let mut conn = self.redis_pool.get().await.map_err(CacheError::from)?;
-        let _: Result<(), _> = conn.del(key).await;
+    pub async fn delete(&self, key: &K) -> Result<(), CacheError> {
+        delete_key(&self.backend, &self.moka_cache, self.invalidator.as_ref(), &key.cache_key()).await;
         Ok(())
     }
+
+    /// Refetches a key from upstream if it is missing or turns stale within `ahead`.
+    ///
+    /// Returns whether the key was refetched, a cached "not found" included.
+    pub async fn refresh_ahead<F, Fut>(&self, key: &K, ahead: Duration, http_fetch: F) -> Result<bool, CacheError>
+    where
+        F: FnOnce(Client, Validators) -> Fut,
+        Fut: Future<Output = Result<Fetched<K::Value>, ReqwestError>>,
+    {
+        let key = &key.cache_key();
+        let now = Utc::now().timestamp_millis();
+
+        // Redis is shared by every instance, so a key refreshed by another one is skipped here
+        let cached = match self.redis_get(key).await {
+            Some(cached) => cached.map(|(stored, remaining)| (stored, Some(remaining))),
+            None => self.memory_get(key).await.map(|(stored, _)| (stored, None)),
+        };
+        let fresh_until = match &cached {
+            Some((Stored::Found { fresh_until, .. }, _)) => Some(*fresh_until),
+            Some((Stored::NotFound, Some(remaining))) => {
+                Some(now.saturating_add(i64::try_from(remaining.as_millis()).unwrap_or(i64::MAX)))
+            }
+            Some((Stored::NotFound, None)) | None => None,
+        };
+        if fresh_until.is_some_and(|fresh_until| fresh_until - now > ahead.as_millis() as i64) {
+            return Ok(false);
+        }
+
+        let revalidation = cached.and_then(|(stored, _)| stored.revalidation());
+        match self.fetch_and_store(key, revalidation, http_fetch).await {
+            Ok(_) | Err(CacheError::NotFound) => {
+                self.publish_invalidation(key).await;
+                Ok(true)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn publish_invalidation(&self, key: &str) {
+        if let Some(invalidator) = &self.invalidator {
+            invalidator.publish(Invalidation::Key { key: key.to_string() }).await;
+        }
+    }
+
+    /// Writes a value that is fresh for the Redis TTL to both tiers
+    async fn write_found(
+        &self,
+        key: &str,
+        data: Arc<K::Value>,
+        validators: Option<Arc<Validators>>,
+        token: Option<u64>,
+    ) -> Result<Stored<K::Value>, CacheError> {
+        let fresh_for = self.policy.jittered(self.policy.redis_ttl);
+        let fresh_until = Utc::now().timestamp_millis() + fresh_for.as_millis() as i64;
+        let encoded = self.policy.codec.encode(&CacheEntry {
+            fresh_until,
+            data: &*data,
+            validators: validators.as_deref().cloned(),
+        })?;
+
+        let stored = Stored::Found { data, fresh_until, validators };
+        let redis_ttl = fresh_for + self.policy.stale_ttl;
+        self.memory_insert(key, stored.clone().erase(), redis_ttl).await;
+        if let Some(last_known_ttl) = self.last_known_ttl() {
+            self.store(&last_known_key(key), encoded.clone(), last_known_ttl.max(redis_ttl), None).await;
+        }
+        self.store(key, encoded, redis_ttl, token).await;
+        Ok(stored)
+    }
+
+    /// Writes a "not found" marker that lives for the negative TTL to both tiers
+    async fn write_not_found(&self, key: &str, token: Option<u64>) {
+        let ttl = self.policy.jittered(self.policy.negative_ttl);
+        self.memory_insert(key, Stored::NotFound, ttl).await;
+        self.store(key, NOT_FOUND_MARKER.to_vec(), ttl, token).await;
+        // The upstream no longer has the value, so an outage must not bring it back
+        if self.last_known_ttl().is_some() {
+            let _ = self.backend.del(&last_known_key(key)).await;
+        }
+    }
+
+    /// How long the last known copy of each value is kept, `None` without a circuit breaker
+    fn last_known_ttl(&self) -> Option<Duration> {
+        self.circuit_breaker.as_ref().and_then(CircuitBreaker::last_known_ttl)
+    }
+
+    /// Decodes a value read from Redis; `None` if it is unreadable.
+    ///
+    /// Unreadable values usually mean `K::Value` changed shape without a `K::VERSION` bump.
+    fn decode(key: &str, cached: &[u8]) -> Option<Stored<K::Value>> {
+        if cached == NOT_FOUND_MARKER {
+            return Some(Stored::NotFound);
+        }
+        match codec::decode::<CacheEntry<K::Value>>(cached) {
+            Ok(entry) => Some(Stored::Found {
+                data: Arc::new(entry.data),
+                fresh_until: entry.fresh_until,
+                validators: entry.validators.map(Arc::new),
+            }),
+            Err(e) => {
+                warn!("Cached value of '{}' could not be deserialized, treating as a miss: {}", key, e);
+                counter!("cache_deserialization_errors_total", "namespace" => K::NAMESPACE).increment(1);
+                None
+            }
+        }
+    }
+
+    /// Reads a decoded value from Moka, with the time it has left there
+    async fn memory_get(&self, key: &str) -> Option<(Stored<K::Value>, Duration)> {
+        let entry = self.moka_cache.get(key).await?;
+        let remaining = entry.remaining();
+        Some((entry.value.downcast()?, remaining))
+    }
+
+    /// Inserts into Moka for the memory TTL, capped at the time left in Redis.
+    /// Whether the value is stale is decided by its `fresh_until`, not by this TTL.
+    async fn memory_insert(&self, key: &str, value: Stored<dyn Any + Send + Sync>, redis_remaining: Duration) {
+        let ttl = self.policy.memory_ttl.min(redis_remaining);
+        let expires_at = Instant::now() + ttl;
+        self.moka_cache.insert(key.to_string(), MemoryEntry { value, ttl, expires_at }).await;
+    }
+
+    /// Reads and decodes a value and its remaining TTL from the backend; `None` while it is unavailable
+    async fn redis_get(&self, key: &str) -> Option<Option<(Stored<K::Value>, Duration)>> {
+        match self.backend.get_with_ttl(key).await {
+            Ok(None) => Some(None),
+            Ok(Some((cached, remaining))) => match compression::decode(cached) {
+                Ok(cached) => Some(Self::decode(key, &cached).map(|stored| (stored, remaining))),
+                Err(e) => {
+                    warn!("Cached value of '{}' could not be decompressed, treating as a miss: {}", key, e);
+                    counter!("cache_deserialization_errors_total", "namespace" => K::NAMESPACE).increment(1);
+                    Some(None)
+                }
+            },
+            Err(BackendError::Unavailable) => None,
+            Err(BackendError::Redis(e)) => {
+                debug!("Reading '{}' from Redis failed, treating as a miss: {}", key, e);
+                Some(None)
+            }
+        }
+    }
+
+    /// Writes a value to the backend, compressed if large enough and fenced by the
+    /// refresh lock token when one is held; a no-op while the backend is unavailable
+    async fn store(&self, key: &str, value: Vec<u8>, ttl: Duration, token: Option<u64>) {
+        let value = compression::encode(value, &self.policy.compression);
+        match token {
+            Some(token) => {
+                self.backend.fenced_set_ex(key, token, &value, ttl).await;
+            }
+            None => {
+                let _ = self.backend.set_ex(key, &value, ttl).await;
+            }
+        }
+    }
+}
+
+/// Key of the long-lived copy of `key` served while its upstream circuit is open
+pub fn last_known_key(key: &str) -> String {
+    format!("{}:last", key)
+}
+
+/// Deletes a key from the backend and from the Moka cache of this and, with an
+/// invalidator, every other instance. Returns whether the backend held the key.
+pub async fn delete_key<B: CacheBackend>(
+    backend: &B,
+    moka_cache: &MemoryCache,
+    invalidator: Option<&Invalidator>,
+    key: &str,
+) -> bool {
+    moka_cache.invalidate(key).await;
+    let deleted = backend.del(key).await.unwrap_or(false);
+    let _ = backend.del(&last_known_key(key)).await;
+
+    if let Some(invalidator) = invalidator {
+        invalidator.publish(Invalidation::Key { key: key.to_string() }).await;
+    }
+    deleted
 }
 
 #[macro_export]
 macro_rules! cache_http_request {
     ($cache:expr, $key:expr, $request:expr) => {
-        $cache.get_or_fetch($key, |client| {
+        $cache.get_or_fetch($key, move |client, validators| {
             let fut = async move {
-                $request(client).await
+                $request(client, validators).await
             };
             fut
         }).await
     };
 
     ($cache:expr, $key:expr, $request:expr, $error_handler:expr) => {
-        $cache.get_or_fetch($key, |client| {
+        $cache.get_or_fetch($key, move |client, validators| {
             let fut = async move {
-                $request(client).await
+                $request(client, validators).await
             };
             fut
         }).await.map_err($error_handler)
@@ -199,24 +914,280 @@ macro_rules! cache_http_request {
 }
 
 pub trait JsonResponseExt {
-    async fn json_cached<T>(self) -> Result<Option<T>, ReqwestError>
+    /// Decodes an upstream response for the cache, along with its validators
+    async fn json_cached<T>(self) -> Result<Fetched<T>, ReqwestError>
     where
         T: DeserializeOwned;
 }
 
 impl JsonResponseExt for reqwest::Response {
-    async fn json_cached<T>(self) -> Result<Option<T>, ReqwestError>
+    async fn json_cached<T>(self) -> Result<Fetched<T>, ReqwestError>
     where
         T: DeserializeOwned,
     {
         if self.status().is_success() {
-            self.json::<T>().await.map(Some)
+            let validators = Validators::from_headers(self.headers());
+            let data = self.json::<T>().await?;
+            Ok(Fetched::Found { data, validators })
+        } else if self.status() == StatusCode::NOT_MODIFIED {
+            // 304 to If-None-Match / If-Modified-Since - the cached value is still current
+            Ok(Fetched::NotModified)
+        } else if matches!(self.status(), StatusCode::REQUEST_TIMEOUT | StatusCode::TOO_MANY_REQUESTS) {
+            // 408 and 429 say nothing about the resource and must not be cached as missing
+            Err(self.error_for_status().unwrap_err())
         } else if self.status().is_client_error() {
-            // 4xx responses - treat as "not found"
-            Ok(None)
+            // Other 4xx responses - treat as "not found"
+            Ok(Fetched::NotFound)
         } else {
             // Other errors - propagate
-            self.error_for_status()?.json::<T>().await.map(Some)
+            let validators = Validators::from_headers(self.headers());
+            let data = self.error_for_status()?.json::<T>().await?;
+            Ok(Fetched::Found { data, validators })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    };
+
+    use super::*;
+    use crate::config::CircuitBreakerConfig;
+    use crate::util::cache_backend::{MemoryBackend, NoopBackend};
+
+    struct TestKey(u32);
+
+    impl CacheKey for TestKey {
+        type Value = String;
+        const NAMESPACE: &'static str = "test";
+        const VERSION: u32 = 1;
+
+        fn id(&self) -> String {
+            self.0.to_string()
+        }
+    }
+
+    fn policy(redis_ttl: Duration) -> CachePolicy {
+        CachePolicy {
+            memory_ttl: Duration::from_secs(60),
+            redis_ttl,
+            stale_ttl: Duration::from_secs(60),
+            negative_ttl: Duration::from_secs(60),
+            jitter: 0.0,
+            codec: CodecKind::Json,
+            compression: CompressionConfig::default(),
+        }
+    }
+
+    /// Wrapper with its own Moka tier over `backend`, like a separate instance
+    fn wrapper(backend: &MemoryBackend, redis_ttl: Duration) -> CacheWrapper<TestKey, MemoryBackend> {
+        let moka_cache = Cache::builder().expire_after(MemoryExpiry).build();
+        CacheWrapper::new(backend.clone(), moka_cache, CacheFlights::new(), policy(redis_ttl), Client::new())
+    }
+
+    /// Upstream answering with `value`, `None` for "not found", and counting its calls
+    fn upstream(
+        calls: &Arc<AtomicUsize>,
+        value: Option<&str>,
+    ) -> impl FnOnce(Client, Validators) -> std::future::Ready<Result<Fetched<String>, ReqwestError>> {
+        let calls = calls.clone();
+        let value = value.map(str::to_string);
+        move |_, _| {
+            calls.fetch_add(1, Ordering::SeqCst);
+            std::future::ready(Ok(match value {
+                Some(data) => Fetched::Found { data, validators: Validators::default() },
+                None => Fetched::NotFound,
+            }))
+        }
+    }
+
+    /// Value the backend holds for `key`, decoded from its envelope
+    async fn backend_value(backend: &MemoryBackend, key: &TestKey) -> Option<String> {
+        let bytes = backend.get(&key.cache_key()).await.unwrap()?;
+        let entry: CacheEntry<String> = codec::decode(&compression::decode(bytes).unwrap()).unwrap();
+        Some(entry.data)
+    }
+
+    #[tokio::test]
+    async fn miss_fetches_and_stores_the_value() {
+        let backend = MemoryBackend::new();
+        let cache = wrapper(&backend, Duration::from_secs(60));
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        let value = cache.get_or_fetch(&TestKey(1), upstream(&calls, Some("one"))).await.unwrap();
+
+        assert_eq!(*value, "one");
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert_eq!(backend_value(&backend, &TestKey(1)).await.as_deref(), Some("one"));
+        assert!(backend.pttl(&TestKey(1).cache_key()).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn memory_tier_never_outlives_its_ttl_or_redis() {
+        let backend = MemoryBackend::new();
+        let cache = wrapper(&backend, Duration::from_secs(60));
+        let calls = Arc::new(AtomicUsize::new(0));
+        let key = TestKey(1).cache_key();
+        cache.get_or_fetch(&TestKey(1), upstream(&calls, Some("one"))).await.unwrap();
+        assert_eq!(cache.moka_cache.get(&key).await.unwrap().ttl(), Duration::from_secs(60));
+
+        // Promoted by another instance with only seconds left in Redis
+        backend.set_ex(&key, &backend.get(&key).await.unwrap().unwrap(), Duration::from_secs(3)).await.unwrap();
+        let other = wrapper(&backend, Duration::from_secs(60));
+        other.get_or_fetch(&TestKey(1), upstream(&calls, Some("two"))).await.unwrap();
+
+        assert!(other.moka_cache.get(&key).await.unwrap().ttl() <= Duration::from_secs(3));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn hit_is_served_without_fetching() {
+        let backend = MemoryBackend::new();
+        let cache = wrapper(&backend, Duration::from_secs(60));
+        let calls = Arc::new(AtomicUsize::new(0));
+        cache.get_or_fetch(&TestKey(1), upstream(&calls, Some("one"))).await.unwrap();
+
+        // From Moka on the same instance, and from the backend on another one
+        let memory_hit = cache.get_or_fetch(&TestKey(1), upstream(&calls, Some("two"))).await.unwrap();
+        let other = wrapper(&backend, Duration::from_secs(60));
+        let backend_hit = other.get_or_fetch(&TestKey(1), upstream(&calls, Some("two"))).await.unwrap();
+
+        assert_eq!(*memory_hit, "one");
+        assert_eq!(*backend_hit, "one");
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn stale_is_served_and_refreshed_in_the_background() {
+        let backend = MemoryBackend::new();
+        // Values turn stale as soon as they are written
+        let cache = wrapper(&backend, Duration::ZERO);
+        let calls = Arc::new(AtomicUsize::new(0));
+        cache.get_or_fetch(&TestKey(1), upstream(&calls, Some("old"))).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(5)).await;
+
+        let stale = cache.get_or_fetch(&TestKey(1), upstream(&calls, Some("new"))).await.unwrap();
+        assert_eq!(*stale, "old");
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while backend_value(&backend, &TestKey(1)).await.as_deref() != Some("new") {
+            assert!(Instant::now() < deadline, "stale value was not refreshed");
+            tokio::time::sleep(Duration::from_millis(10)).await;
         }
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn not_found_is_cached_as_a_negative_entry() {
+        let backend = MemoryBackend::new();
+        let cache = wrapper(&backend, Duration::from_secs(60));
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        let first = cache.get_or_fetch(&TestKey(404), upstream(&calls, None)).await;
+        let second = cache.get_or_fetch(&TestKey(404), upstream(&calls, Some("found"))).await;
+        let other = wrapper(&backend, Duration::from_secs(60));
+        let third = other.get_or_fetch(&TestKey(404), upstream(&calls, Some("found"))).await;
+
+        assert!(matches!(first, Err(CacheError::NotFound)));
+        assert!(matches!(second, Err(CacheError::NotFound)));
+        assert!(matches!(third, Err(CacheError::NotFound)));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        let marker = backend.get(&TestKey(404).cache_key()).await.unwrap();
+        assert_eq!(marker.as_deref(), Some(NOT_FOUND_MARKER));
+    }
+
+    #[tokio::test]
+    async fn open_circuit_serves_the_last_known_value_after_the_entry_expired() {
+        let backend = MemoryBackend::new();
+        let breakers = CircuitBreakers::new(CircuitBreakerConfig { failure_threshold: 1, ..Default::default() });
+        let calls = Arc::new(AtomicUsize::new(0));
+        wrapper(&backend, Duration::from_secs(60))
+            .with_circuit_breaker(&breakers, "upstream")
+            .get_or_fetch(&TestKey(1), upstream(&calls, Some("one")))
+            .await
+            .unwrap();
+
+        // The entry expires from Redis, then the upstream goes down
+        backend.del(&TestKey(1).cache_key()).await.unwrap();
+        breakers.for_host("upstream").unwrap().record_failure();
+
+        let other = wrapper(&backend, Duration::from_secs(60)).with_circuit_breaker(&breakers, "upstream");
+        let value = other.get_or_fetch(&TestKey(1), upstream(&calls, Some("two"))).await.unwrap();
+        let missing = other.get_or_fetch(&TestKey(2), upstream(&calls, Some("two"))).await;
+
+        assert_eq!(*value, "one");
+        assert!(matches!(missing, Err(CacheError::CircuitOpen)));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn not_modified_extends_the_entry_without_refetching_the_body() {
+        let backend = MemoryBackend::new();
+        // Values turn stale as soon as they are written
+        let cache = wrapper(&backend, Duration::ZERO);
+        let key = TestKey(1).cache_key();
+        let etag = Validators { etag: Some("\"v1\"".to_string()), last_modified: None };
+        let first = etag.clone();
+        cache
+            .get_or_fetch(&TestKey(1), move |_, _| async move {
+                Ok(Fetched::Found { data: "one".to_string(), validators: first })
+            })
+            .await
+            .unwrap();
+        backend.set_ex(&key, &backend.get(&key).await.unwrap().unwrap(), Duration::from_secs(1)).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(5)).await;
+
+        let sent = Arc::new(Mutex::new(None));
+        let received = sent.clone();
+        let stale = cache
+            .get_or_fetch(&TestKey(1), move |_, validators| async move {
+                *received.lock().unwrap() = Some(validators);
+                Ok(Fetched::NotModified)
+            })
+            .await
+            .unwrap();
+        assert_eq!(*stale, "one");
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while backend.pttl(&key).await.unwrap().unwrap() <= Duration::from_secs(1) {
+            assert!(Instant::now() < deadline, "304 did not extend the entry");
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        let sent = sent.lock().unwrap().clone().unwrap();
+        assert_eq!(sent.etag, etag.etag);
+        assert_eq!(backend_value(&backend, &TestKey(1)).await.as_deref(), Some("one"));
+    }
+
+    #[tokio::test]
+    async fn not_modified_without_validators_is_an_upstream_error() {
+        let backend = MemoryBackend::new();
+        let cache = wrapper(&backend, Duration::from_secs(60));
+
+        let result = cache.get_or_fetch(&TestKey(1), |_, _| async { Ok(Fetched::NotModified) }).await;
+
+        assert!(matches!(result, Err(CacheError::UnexpectedNotModified)));
+        assert!(backend.get(&TestKey(1).cache_key()).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn without_a_shared_tier_values_stay_in_memory() {
+        let moka_cache = Cache::builder().expire_after(MemoryExpiry).build();
+        let cache = CacheWrapper::<TestKey, _>::new(
+            NoopBackend,
+            moka_cache,
+            CacheFlights::new(),
+            policy(Duration::from_secs(60)),
+            Client::new(),
+        );
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        cache.get_or_fetch(&TestKey(1), upstream(&calls, Some("one"))).await.unwrap();
+        let hit = cache.get_or_fetch(&TestKey(1), upstream(&calls, Some("two"))).await.unwrap();
+
+        assert_eq!(*hit, "one");
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
     }
 }
@@ -0,0 +1,308 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Arc, LazyLock, Mutex},
+    time::Duration,
+};
+
+use bb8_redis::redis::{self, AsyncCommands, RedisError, Script};
+use tokio::time::Instant;
+
+use crate::util::{
+    redis_pool::RedisPool,
+    refresh_lock::{self, Acquire},
+};
+
+/// Error returned by a cache backend
+#[derive(Debug)]
+pub enum BackendError {
+    Unavailable,       // The backend is degraded or unreachable, callers skip the tier
+    Redis(RedisError), // A Redis command failed, already reported to the pool
+}
+
+pub type BackendResult<T> = Result<T, BackendError>;
+
+/// Raises the TTL of `KEYS[1]` to `ARGV[1]` milliseconds if it expires sooner,
+/// like `PEXPIRE GT` without needing Redis 7. Returns 1 if the TTL was raised.
+static EXTEND_TTL: LazyLock<Script> = LazyLock::new(|| {
+    Script::new(
+        r#"
+        local ttl = redis.call('PTTL', KEYS[1])
+        if ttl >= 0 and ttl < tonumber(ARGV[1]) then
+            return redis.call('PEXPIRE', KEYS[1], ARGV[1])
+        end
+        return 0
+        "#,
+    )
+});
+
+/// Shared, second cache tier behind the per-instance Moka cache.
+///
+/// Values are opaque bytes; encoding, compression and the freshness envelope
+/// are handled by `CacheWrapper`. Backends that can't coordinate refreshes keep
+/// the default lock methods, which let every instance fetch on its own.
+pub trait CacheBackend: Clone + Send + Sync + 'static {
+    /// Reads a value
+    fn get(&self, key: &str) -> impl Future<Output = BackendResult<Option<Vec<u8>>>> + Send;
+
+    /// Writes a value expiring after `ttl`
+    fn set_ex(&self, key: &str, value: &[u8], ttl: Duration) -> impl Future<Output = BackendResult<()>> + Send;
+
+    /// Writes a value expiring after `ttl` only if the key is missing, returns whether it was written
+    fn set_nx(&self, key: &str, value: &[u8], ttl: Duration) -> impl Future<Output = BackendResult<bool>> + Send;
+
+    /// Deletes a key, returns whether it existed
+    fn del(&self, key: &str) -> impl Future<Output = BackendResult<bool>> + Send;
+
+    /// Makes a key live at least `ttl` from now, never shortening it; returns whether it was extended
+    fn extend_ttl(&self, key: &str, ttl: Duration) -> impl Future<Output = BackendResult<bool>> + Send;
+
+    /// Reads several values at once, in the order of `keys`
+    #[allow(dead_code)]
+    fn mget(&self, keys: &[String]) -> impl Future<Output = BackendResult<Vec<Option<Vec<u8>>>>> + Send;
+
+    /// Time left before a key expires, `None` if it is missing or never expires
+    fn pttl(&self, key: &str) -> impl Future<Output = BackendResult<Option<Duration>>> + Send;
+
+    /// Reads a value with the time it has left, `Duration::MAX` if it never expires
+    fn get_with_ttl(&self, key: &str) -> impl Future<Output = BackendResult<Option<(Vec<u8>, Duration)>>> + Send {
+        async move {
+            let Some(value) = self.get(key).await? else {
+                return Ok(None);
+            };
+            let remaining = self.pttl(key).await?.unwrap_or(Duration::MAX);
+            Ok(Some((value, remaining)))
+        }
+    }
+
+    /// Takes the cross-instance refresh lock for `key`
+    fn acquire_lock(&self, _key: &str, _ttl: Duration) -> impl Future<Output = Acquire> + Send {
+        async { Acquire::Unavailable }
+    }
+
+    /// Writes a value only while the refresh lock still holds `token`, returns whether it was written
+    fn fenced_set_ex(&self, key: &str, _token: u64, value: &[u8], ttl: Duration) -> impl Future<Output = bool> + Send {
+        async move { self.set_ex(key, value, ttl).await.is_ok() }
+    }
+
+    /// Releases the refresh lock if it still holds `token`
+    fn release_lock(&self, _key: &str, _token: u64) -> impl Future<Output = ()> + Send {
+        async {}
+    }
+}
+
+/// Redis through the bb8 pool, skipped while the pool is degraded; reads use the replicas if configured
+impl CacheBackend for RedisPool {
+    async fn get(&self, key: &str) -> BackendResult<Option<Vec<u8>>> {
+        let (mut conn, pool) = self.get_for_read().await.ok_or(BackendError::Unavailable)?;
+        conn.get(key).await.map_err(|e| pool.backend_error(e))
+    }
+
+    async fn set_ex(&self, key: &str, value: &[u8], ttl: Duration) -> BackendResult<()> {
+        let mut conn = self.get().await.ok_or(BackendError::Unavailable)?;
+        conn.pset_ex(key, value, ttl.as_millis() as u64)
+            .await
+            .map_err(|e| self.backend_error(e))
+    }
+
+    async fn set_nx(&self, key: &str, value: &[u8], ttl: Duration) -> BackendResult<bool> {
+        let mut conn = self.get().await.ok_or(BackendError::Unavailable)?;
+        let written: Option<String> = redis::cmd("SET")
+            .arg(key)
+            .arg(value)
+            .arg("NX")
+            .arg("PX")
+            .arg(ttl.as_millis() as u64)
+            .query_async(&mut *conn)
+            .await
+            .map_err(|e| self.backend_error(e))?;
+        Ok(written.is_some())
+    }
+
+    async fn del(&self, key: &str) -> BackendResult<bool> {
+        let mut conn = self.get().await.ok_or(BackendError::Unavailable)?;
+        let deleted: u32 = conn.del(key).await.map_err(|e| self.backend_error(e))?;
+        Ok(deleted > 0)
+    }
+
+    async fn extend_ttl(&self, key: &str, ttl: Duration) -> BackendResult<bool> {
+        let mut conn = self.get().await.ok_or(BackendError::Unavailable)?;
+        let extended: u32 = EXTEND_TTL
+            .key(key)
+            .arg(ttl.as_millis() as u64)
+            .invoke_async(&mut *conn)
+            .await
+            .map_err(|e| self.backend_error(e))?;
+        Ok(extended > 0)
+    }
+
+    async fn mget(&self, keys: &[String]) -> BackendResult<Vec<Option<Vec<u8>>>> {
+        if keys.is_empty() {
+            return Ok(Vec::new());
+        }
+        let (mut conn, pool) = self.get_for_read().await.ok_or(BackendError::Unavailable)?;
+        // MGET explicitly, redis-rs sends a single key as GET and returns it unwrapped
+        redis::cmd("MGET")
+            .arg(keys)
+            .query_async(&mut *conn)
+            .await
+            .map_err(|e| pool.backend_error(e))
+    }
+
+    async fn pttl(&self, key: &str) -> BackendResult<Option<Duration>> {
+        let (mut conn, pool) = self.get_for_read().await.ok_or(BackendError::Unavailable)?;
+        let pttl: i64 = conn.pttl(key).await.map_err(|e| pool.backend_error(e))?;
+        // -2 if the key is missing, -1 if it has no expiry
+        Ok(u64::try_from(pttl).ok().map(Duration::from_millis))
+    }
+
+    async fn get_with_ttl(&self, key: &str) -> BackendResult<Option<(Vec<u8>, Duration)>> {
+        let (mut conn, pool) = self.get_for_read().await.ok_or(BackendError::Unavailable)?;
+        let (value, pttl): (Option<Vec<u8>>, i64) = redis::pipe()
+            .get(key)
+            .pttl(key)
+            .query_async(&mut *conn)
+            .await
+            .map_err(|e| pool.backend_error(e))?;
+
+        // A negative PTTL means the key has no expiry
+        let remaining = u64::try_from(pttl).map_or(Duration::MAX, Duration::from_millis);
+        Ok(value.map(|value| (value, remaining)))
+    }
+
+    async fn acquire_lock(&self, key: &str, ttl: Duration) -> Acquire {
+        refresh_lock::acquire(self, key, ttl).await
+    }
+
+    async fn fenced_set_ex(&self, key: &str, token: u64, value: &[u8], ttl: Duration) -> bool {
+        refresh_lock::fenced_set(self, key, token, value, ttl).await
+    }
+
+    async fn release_lock(&self, key: &str, token: u64) {
+        refresh_lock::release(self, key, token).await
+    }
+}
+
+impl RedisPool {
+    fn backend_error(&self, error: RedisError) -> BackendError {
+        self.report_error(&error);
+        BackendError::Redis(error)
+    }
+}
+
+/// Values of the in-memory backend with their expiry
+type Entries = HashMap<String, (Vec<u8>, Instant)>;
+
+/// In-process fake of the shared tier, for tests and local runs without Redis
+#[cfg_attr(not(test), allow(dead_code))]
+#[derive(Clone, Default)]
+pub struct MemoryBackend {
+    entries: Arc<Mutex<Entries>>,
+}
+
+#[cfg_attr(not(test), allow(dead_code))]
+impl MemoryBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs `f` on the live entry for `key`, dropping it first if it has expired
+    fn with_entry<T>(&self, key: &str, f: impl FnOnce(Option<&(Vec<u8>, Instant)>) -> T) -> T {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        if entries.get(key).is_some_and(|(_, expires_at)| *expires_at <= Instant::now()) {
+            entries.remove(key);
+        }
+        f(entries.get(key))
+    }
+}
+
+impl CacheBackend for MemoryBackend {
+    async fn get(&self, key: &str) -> BackendResult<Option<Vec<u8>>> {
+        Ok(self.with_entry(key, |entry| entry.map(|(value, _)| value.clone())))
+    }
+
+    async fn set_ex(&self, key: &str, value: &[u8], ttl: Duration) -> BackendResult<()> {
+        let expires_at = Instant::now() + ttl;
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.insert(key.to_string(), (value.to_vec(), expires_at));
+        Ok(())
+    }
+
+    async fn set_nx(&self, key: &str, value: &[u8], ttl: Duration) -> BackendResult<bool> {
+        let now = Instant::now();
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        if entries.get(key).is_some_and(|(_, expires_at)| *expires_at > now) {
+            return Ok(false);
+        }
+        entries.insert(key.to_string(), (value.to_vec(), now + ttl));
+        Ok(true)
+    }
+
+    async fn del(&self, key: &str) -> BackendResult<bool> {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let removed = entries.remove(key);
+        Ok(removed.is_some_and(|(_, expires_at)| expires_at > Instant::now()))
+    }
+
+    async fn extend_ttl(&self, key: &str, ttl: Duration) -> BackendResult<bool> {
+        let expires_at = Instant::now() + ttl;
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        match entries.get_mut(key) {
+            Some((_, current)) if *current > Instant::now() && *current < expires_at => {
+                *current = expires_at;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn mget(&self, keys: &[String]) -> BackendResult<Vec<Option<Vec<u8>>>> {
+        let mut values = Vec::with_capacity(keys.len());
+        for key in keys {
+            values.push(self.get(key).await?);
+        }
+        Ok(values)
+    }
+
+    async fn pttl(&self, key: &str) -> BackendResult<Option<Duration>> {
+        Ok(self.with_entry(key, |entry| {
+            entry.map(|(_, expires_at)| expires_at.saturating_duration_since(Instant::now()))
+        }))
+    }
+}
+
+/// Backend that stores nothing, every read misses; runs the service on Moka alone
+#[cfg_attr(not(test), allow(dead_code))]
+#[derive(Clone, Copy, Default)]
+pub struct NoopBackend;
+
+impl CacheBackend for NoopBackend {
+    async fn get(&self, _key: &str) -> BackendResult<Option<Vec<u8>>> {
+        Ok(None)
+    }
+
+    async fn set_ex(&self, _key: &str, _value: &[u8], _ttl: Duration) -> BackendResult<()> {
+        Ok(())
+    }
+
+    // Nothing is remembered, so a write can't be known to be the first
+    async fn set_nx(&self, _key: &str, _value: &[u8], _ttl: Duration) -> BackendResult<bool> {
+        Err(BackendError::Unavailable)
+    }
+
+    async fn del(&self, _key: &str) -> BackendResult<bool> {
+        Ok(false)
+    }
+
+    async fn extend_ttl(&self, _key: &str, _ttl: Duration) -> BackendResult<bool> {
+        Ok(false)
+    }
+
+    async fn mget(&self, keys: &[String]) -> BackendResult<Vec<Option<Vec<u8>>>> {
+        Ok(vec![None; keys.len()])
+    }
+
+    async fn pttl(&self, _key: &str) -> BackendResult<Option<Duration>> {
+        Ok(None)
+    }
+}
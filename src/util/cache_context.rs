@@ -0,0 +1,109 @@
+use std::sync::Arc;
+
+use reqwest::Client;
+use tokio_util::task::TaskTracker;
+
+use crate::{
+    config::AppConfig,
+    util::{
+        cache::{CacheFlights, CachePolicy, CacheWrapper, MemoryCache},
+        cache_backend::CacheBackend,
+        cache_key::CacheKey,
+        circuit_breaker::CircuitBreakers,
+        invalidation::Invalidator,
+        redis_pool::RedisPool,
+        retry::RetryPolicy,
+    },
+};
+
+/// Dependencies shared by every `CacheWrapper`, passed around as one extension
+/// so handlers and the warmer build their wrappers the same way
+#[derive(Clone)]
+pub struct CacheContext<B = RedisPool> {
+    backend: B,                         // Shared tier, Redis unless testing
+    moka_cache: MemoryCache,            // Moka in-memory cache
+    flights: CacheFlights,              // In-flight upstream fetches
+    http_client: Client,                // Reqwest HTTP client
+    invalidator: Option<Invalidator>,   // Propagates writes and deletes to other instances
+    circuit_breakers: CircuitBreakers,  // Per-host circuit breakers
+    retry: RetryPolicy,                 // Retry policy of upstream requests
+    tasks: TaskTracker,                 // Background refreshes of every wrapper
+    config: Arc<AppConfig>,
+}
+
+impl<B: CacheBackend> CacheContext<B> {
+    /// Context with fresh coalescing, circuit breakers and retry budget, set up from `config`
+    pub fn new(backend: B, moka_cache: MemoryCache, http_client: Client, config: Arc<AppConfig>) -> Self {
+        Self {
+            backend,
+            moka_cache,
+            flights: CacheFlights::new(),
+            http_client,
+            invalidator: None,
+            circuit_breakers: CircuitBreakers::new(config.http_client.circuit_breaker.clone()),
+            retry: RetryPolicy::new(&config.http_client),
+            tasks: TaskTracker::new(),
+            config,
+        }
+    }
+
+    /// Publishes the writes of every wrapper to the other instances
+    pub fn with_invalidator(mut self, invalidator: Invalidator) -> Self {
+        self.invalidator = Some(invalidator);
+        self
+    }
+
+    /// Runs background refreshes on `tasks`, so shutdown waits for them
+    pub fn with_task_tracker(mut self, tasks: TaskTracker) -> Self {
+        self.tasks = tasks;
+        self
+    }
+
+    /// Wrapper for keys of type `K` fetched from `upstream_host`, with the policy,
+    /// refresh lock and circuit breaker from the config
+    pub fn wrapper<K: CacheKey + 'static>(&self, upstream_host: &str) -> CacheWrapper<K, B> {
+        let wrapper = CacheWrapper::<K, B>::new(
+            self.backend.clone(),
+            self.moka_cache.clone(),
+            self.flights.clone(),
+            CachePolicy::from_config(&self.config.cache, K::NAMESPACE),
+            self.http_client.clone(),
+        );
+        let wrapper = match &self.invalidator {
+            Some(invalidator) => wrapper.with_invalidator(invalidator.clone()),
+            None => wrapper,
+        };
+        wrapper
+            .with_refresh_lock(&self.config.cache.refresh_lock)
+            .with_circuit_breaker(&self.circuit_breakers, upstream_host)
+            .with_task_tracker(self.tasks.clone())
+    }
+
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    pub fn moka_cache(&self) -> &MemoryCache {
+        &self.moka_cache
+    }
+
+    pub fn flights(&self) -> &CacheFlights {
+        &self.flights
+    }
+
+    pub fn http_client(&self) -> &Client {
+        &self.http_client
+    }
+
+    pub fn circuit_breakers(&self) -> &CircuitBreakers {
+        &self.circuit_breakers
+    }
+
+    pub fn retry(&self) -> &RetryPolicy {
+        &self.retry
+    }
+
+    pub fn config(&self) -> &Arc<AppConfig> {
+        &self.config
+    }
+}
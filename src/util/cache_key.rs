@@ -0,0 +1,131 @@
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Typed key of a cache entry, rendered as `{namespace}:v{version}:{id}`.
+///
+/// The version is part of every key, so bumping it after `Value` changes shape
+/// makes a deploy ignore all entries written with the old schema instead of
+/// failing to deserialize them.
+pub trait CacheKey {
+    /// Type stored under this key
+    type Value: Serialize + DeserializeOwned + Send + Sync + 'static;
+
+    /// Key prefix, also the name of the cache policy in `[cache.policies]`
+    const NAMESPACE: &'static str;
+
+    /// Schema version of `Value`, bump it to invalidate every entry of the type
+    const VERSION: u32;
+
+    /// Identifies the entry within its namespace
+    fn id(&self) -> String;
+
+    /// Full key used in both tiers
+    fn cache_key(&self) -> String {
+        format!("{}:v{}:{}", Self::NAMESPACE, Self::VERSION, self.id())
+    }
+}
+
+/// Whether `key` has the `{namespace}:v{version}:{id}` shape of a cache key,
+/// as opposed to rate limit buckets, nonces or locks sharing the same Redis
+pub fn is_cache_key(key: &str) -> bool {
+    let mut parts = key.splitn(3, ':');
+    let (Some(namespace), Some(version), Some(_)) = (parts.next(), parts.next(), parts.next()) else {
+        return false;
+    };
+    !namespace.is_empty()
+        && version
+            .strip_prefix('v')
+            .is_some_and(|number| !number.is_empty() && number.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// Matches `key` against a glob supporting `*` and `?`, the subset of Redis
+/// `MATCH` syntax accepted by the admin API
+pub fn glob_match(pattern: &str, key: &str) -> bool {
+    let (pattern, key) = (pattern.as_bytes(), key.as_bytes());
+    let (mut p, mut k) = (0, 0);
+    // Position of the last `*` and the key position it was tried at
+    let mut backtrack = None;
+
+    while k < key.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                backtrack = Some((p, k));
+                p += 1;
+            }
+            Some(&c) if c == b'?' || c == key[k] => {
+                p += 1;
+                k += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    p = star + 1;
+                    k = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Widget(u32);
+
+    impl CacheKey for Widget {
+        type Value = String;
+        const NAMESPACE: &'static str = "widget";
+        const VERSION: u32 = 3;
+
+        fn id(&self) -> String {
+            self.0.to_string()
+        }
+    }
+
+    #[test]
+    fn keys_carry_namespace_version_and_id() {
+        assert_eq!(Widget(42).cache_key(), "widget:v3:42");
+        assert!(is_cache_key(&Widget(42).cache_key()));
+    }
+
+    #[test]
+    fn only_versioned_keys_are_cache_keys() {
+        assert!(is_cache_key("users:v1:all"));
+        assert!(is_cache_key("user:v12:7:extra"));
+
+        assert!(!is_cache_key("users:v1"));
+        assert!(!is_cache_key(":v1:all"));
+        assert!(!is_cache_key("users:1:all"));
+        assert!(!is_cache_key("users:v:all"));
+        assert!(!is_cache_key("users:v1a:all"));
+        assert!(!is_cache_key("ratelimit:127.0.0.1:/v1/users"));
+        assert!(!is_cache_key("nonce:abc"));
+    }
+
+    #[test]
+    fn glob_matches_stars_and_single_characters() {
+        assert!(glob_match("*", ""));
+        assert!(glob_match("*", "user:v1:7"));
+        assert!(glob_match("user:v1:*", "user:v1:7"));
+        assert!(glob_match("user:v?:7", "user:v1:7"));
+        assert!(glob_match("*:v1:*", "users:v1:all"));
+        assert!(glob_match("u*r*:*", "users:v1:all"));
+        assert!(glob_match("user:v1:7**", "user:v1:7"));
+
+        assert!(!glob_match("user:v1:*", "users:v1:all"));
+        assert!(!glob_match("user:v?:7", "user:v12:7"));
+        assert!(!glob_match("user:v1:7", "user:v1:70"));
+        assert!(!glob_match("*:v2", "users:v1"));
+        assert!(!glob_match("", "users:v1:all"));
+    }
+
+    #[test]
+    fn glob_backtracks_past_partial_matches() {
+        assert!(glob_match("*ab", "aab"));
+        assert!(glob_match("*a*b", "xaxxab"));
+        assert!(!glob_match("*ab", "abba"));
+    }
+}
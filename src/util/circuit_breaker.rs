@@ -0,0 +1,314 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use metrics::{counter, gauge};
+use reqwest::Error as ReqwestError;
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+use crate::config::CircuitBreakerConfig;
+
+/// State of the circuit of one upstream host
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    Closed,   // Fetches go through, consecutive failures are counted
+    Open,     // Fetches fail fast until `open_secs` have passed
+    HalfOpen, // One trial fetch at a time decides whether to close again
+}
+
+impl CircuitState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CircuitState::Closed => "closed",
+            CircuitState::Open => "open",
+            CircuitState::HalfOpen => "half_open",
+        }
+    }
+
+    /// Value of the `upstream_circuit_state` gauge
+    fn gauge_value(&self) -> f64 {
+        match self {
+            CircuitState::Closed => 0.0,
+            CircuitState::HalfOpen => 1.0,
+            CircuitState::Open => 2.0,
+        }
+    }
+}
+
+struct Circuit {
+    state: CircuitState,
+    failures: u32,                  // Consecutive failures while closed
+    successes: u32,                 // Consecutive successful trials while half-open
+    opened_at: Instant,             // When the circuit last opened
+    trial_started: Option<Instant>, // Trial in flight while half-open
+}
+
+/// Circuit breakers of every upstream host, shared by all cache wrappers
+#[derive(Clone)]
+pub struct CircuitBreakers {
+    config: Arc<CircuitBreakerConfig>,
+    hosts: Arc<Mutex<HashMap<String, CircuitBreaker>>>,
+}
+
+impl CircuitBreakers {
+    pub fn new(config: CircuitBreakerConfig) -> Self {
+        Self {
+            config: Arc::new(config),
+            hosts: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Breaker of `host`, created closed on first use; `None` when disabled
+    pub fn for_host(&self, host: &str) -> Option<CircuitBreaker> {
+        if !self.config.enabled {
+            return None;
+        }
+
+        let mut hosts = self.hosts.lock().unwrap_or_else(|e| e.into_inner());
+        let breaker = hosts.entry(host.to_string()).or_insert_with(|| {
+            gauge!("upstream_circuit_state", "host" => host.to_string()).set(CircuitState::Closed.gauge_value());
+            CircuitBreaker {
+                host: host.to_string(),
+                config: self.config.clone(),
+                circuit: Arc::new(Mutex::new(Circuit {
+                    state: CircuitState::Closed,
+                    failures: 0,
+                    successes: 0,
+                    opened_at: Instant::now(),
+                    trial_started: None,
+                })),
+            }
+        });
+        Some(breaker.clone())
+    }
+
+    /// State of every host seen so far, sorted by host
+    pub fn states(&self) -> Vec<(String, CircuitState)> {
+        let hosts = self.hosts.lock().unwrap_or_else(|e| e.into_inner());
+        let mut states: Vec<_> = hosts
+            .values()
+            .map(|breaker| (breaker.host.clone(), breaker.state()))
+            .collect();
+        states.sort_by(|(a, _), (b, _)| a.cmp(b));
+        states
+    }
+}
+
+/// Breaker of a single upstream host
+#[derive(Clone)]
+pub struct CircuitBreaker {
+    host: String,
+    config: Arc<CircuitBreakerConfig>,
+    circuit: Arc<Mutex<Circuit>>,
+}
+
+impl CircuitBreaker {
+    pub fn state(&self) -> CircuitState {
+        self.lock().state
+    }
+
+    /// How long the last known value of each key is kept for outages, `None` if it isn't
+    pub fn last_known_ttl(&self) -> Option<Duration> {
+        (self.config.last_known_ttl_secs > 0).then(|| Duration::from_secs(self.config.last_known_ttl_secs))
+    }
+
+    /// Whether a fetch may go to the upstream now. Once `open_secs` have passed,
+    /// an open circuit turns half-open and lets a single trial through.
+    pub fn try_acquire(&self) -> bool {
+        let open_for = Duration::from_secs(self.config.open_secs);
+        let mut circuit = self.lock();
+        let allowed = match circuit.state {
+            CircuitState::Closed => true,
+            CircuitState::Open if circuit.opened_at.elapsed() >= open_for => {
+                self.transition(&mut circuit, CircuitState::HalfOpen);
+                circuit.trial_started = Some(Instant::now());
+                true
+            }
+            CircuitState::Open => false,
+            // A trial that never reported back, e.g. cancelled, is replaced after `open_secs`
+            CircuitState::HalfOpen => match circuit.trial_started {
+                Some(started) if started.elapsed() < open_for => false,
+                _ => {
+                    circuit.trial_started = Some(Instant::now());
+                    true
+                }
+            },
+        };
+
+        if !allowed {
+            counter!("upstream_circuit_rejections_total", "host" => self.host.clone()).increment(1);
+        }
+        allowed
+    }
+
+    /// Records an upstream response, whatever its content
+    pub fn record_success(&self) {
+        let mut circuit = self.lock();
+        match circuit.state {
+            CircuitState::Closed => circuit.failures = 0,
+            CircuitState::HalfOpen => {
+                circuit.trial_started = None;
+                circuit.successes += 1;
+                if circuit.successes >= self.config.success_threshold {
+                    self.transition(&mut circuit, CircuitState::Closed);
+                }
+            }
+            // A fetch that started before the circuit opened
+            CircuitState::Open => {}
+        }
+    }
+
+    /// Records a connect error, timeout or 5xx from the upstream
+    pub fn record_failure(&self) {
+        let mut circuit = self.lock();
+        match circuit.state {
+            CircuitState::Closed => {
+                circuit.failures += 1;
+                if circuit.failures >= self.config.failure_threshold {
+                    self.transition(&mut circuit, CircuitState::Open);
+                }
+            }
+            CircuitState::HalfOpen => self.transition(&mut circuit, CircuitState::Open),
+            CircuitState::Open => {}
+        }
+    }
+
+    fn transition(&self, circuit: &mut Circuit, state: CircuitState) {
+        circuit.state = state;
+        circuit.failures = 0;
+        circuit.successes = 0;
+        circuit.trial_started = None;
+
+        match state {
+            CircuitState::Open => {
+                circuit.opened_at = Instant::now();
+                warn!("Circuit to '{}' opened, failing fast for {}s", self.host, self.config.open_secs);
+            }
+            CircuitState::HalfOpen => info!("Circuit to '{}' half-open, trying the upstream again", self.host),
+            CircuitState::Closed => info!("Circuit to '{}' closed", self.host),
+        }
+        gauge!("upstream_circuit_state", "host" => self.host.clone()).set(state.gauge_value());
+        counter!("upstream_circuit_transitions_total", "host" => self.host.clone(), "state" => state.as_str())
+            .increment(1);
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Circuit> {
+        self.circuit.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Whether an upstream error counts against the circuit: connect errors, timeouts and 5xx.
+/// Other errors, such as an unparsable body, mean the host did answer.
+pub fn is_upstream_failure(error: &ReqwestError) -> bool {
+    error.is_connect() || error.is_timeout() || error.status().is_some_and(|status| status.is_server_error())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn breaker(failure_threshold: u32, success_threshold: u32) -> CircuitBreaker {
+        let config = CircuitBreakerConfig { failure_threshold, open_secs: 30, success_threshold, ..Default::default() };
+        CircuitBreakers::new(config).for_host("upstream").unwrap()
+    }
+
+    /// Opens the circuit and waits out its cooldown, so the next acquire is the trial
+    async fn half_open(breaker: &CircuitBreaker) {
+        breaker.record_failure();
+        assert_eq!(breaker.state(), CircuitState::Open);
+        tokio::time::advance(Duration::from_secs(30)).await;
+    }
+
+    #[test]
+    fn opens_at_the_failure_threshold() {
+        let breaker = breaker(3, 1);
+
+        breaker.record_failure();
+        breaker.record_failure();
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        assert!(breaker.try_acquire());
+
+        breaker.record_failure();
+        assert_eq!(breaker.state(), CircuitState::Open);
+        assert!(!breaker.try_acquire());
+    }
+
+    #[test]
+    fn a_success_resets_the_failure_count() {
+        let breaker = breaker(2, 1);
+
+        breaker.record_failure();
+        breaker.record_success();
+        breaker.record_failure();
+
+        assert_eq!(breaker.state(), CircuitState::Closed);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn turns_half_open_after_the_cooldown() {
+        let breaker = breaker(1, 1);
+        breaker.record_failure();
+
+        tokio::time::advance(Duration::from_secs(29)).await;
+        assert!(!breaker.try_acquire());
+        assert_eq!(breaker.state(), CircuitState::Open);
+
+        tokio::time::advance(Duration::from_secs(1)).await;
+        assert!(breaker.try_acquire());
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn lets_a_single_trial_through_while_half_open() {
+        let breaker = breaker(1, 1);
+        half_open(&breaker).await;
+
+        assert!(breaker.try_acquire());
+        assert!(!breaker.try_acquire());
+        assert!(!breaker.try_acquire());
+
+        // A trial that never reports back is replaced after the cooldown
+        tokio::time::advance(Duration::from_secs(30)).await;
+        assert!(breaker.try_acquire());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn closes_after_successful_trials() {
+        let breaker = breaker(1, 2);
+        half_open(&breaker).await;
+
+        assert!(breaker.try_acquire());
+        breaker.record_success();
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+
+        assert!(breaker.try_acquire());
+        breaker.record_success();
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        assert!(breaker.try_acquire() && breaker.try_acquire());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn reopens_when_the_trial_fails() {
+        let breaker = breaker(1, 1);
+        half_open(&breaker).await;
+
+        assert!(breaker.try_acquire());
+        breaker.record_failure();
+
+        assert_eq!(breaker.state(), CircuitState::Open);
+        assert!(!breaker.try_acquire());
+        tokio::time::advance(Duration::from_secs(30)).await;
+        assert!(breaker.try_acquire());
+    }
+
+    #[test]
+    fn disabled_breakers_are_not_created() {
+        let breakers = CircuitBreakers::new(CircuitBreakerConfig { enabled: false, ..Default::default() });
+
+        assert!(breakers.for_host("upstream").is_none());
+        assert!(breakers.states().is_empty());
+    }
+}
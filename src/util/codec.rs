@@ -0,0 +1,193 @@
+use std::fmt;
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+/// Tag byte in front of MessagePack-encoded values
+const MESSAGE_PACK_TAG: u8 = 0x10;
+
+/// Tag byte in front of bincode-encoded values
+const BINCODE_TAG: u8 = 0x11;
+
+/// Error raised while encoding or decoding a cached value
+#[derive(Debug)]
+pub struct CodecError(Box<dyn std::error::Error + Send + Sync>);
+
+impl fmt::Display for CodecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl<E: std::error::Error + Send + Sync + 'static> From<E> for CodecError {
+    fn from(err: E) -> Self {
+        CodecError(Box::new(err))
+    }
+}
+
+/// Serialization format of cached values.
+///
+/// Every format except JSON writes a tag byte first, so a value can always be
+/// decoded regardless of the format its wrapper is configured with now.
+pub trait CacheCodec {
+    /// Byte written before each value, `None` for JSON which needs none
+    const TAG: Option<u8>;
+
+    /// Appends the encoded value to `buf`
+    fn encode_into<T: Serialize>(value: &T, buf: &mut Vec<u8>) -> Result<(), CodecError>;
+
+    /// Decodes a value, without its tag byte
+    fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, CodecError>;
+}
+
+pub struct JsonCodec;
+
+impl CacheCodec for JsonCodec {
+    const TAG: Option<u8> = None;
+
+    fn encode_into<T: Serialize>(value: &T, buf: &mut Vec<u8>) -> Result<(), CodecError> {
+        Ok(serde_json::to_writer(buf, value)?)
+    }
+
+    fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, CodecError> {
+        Ok(serde_json::from_slice(bytes)?)
+    }
+}
+
+/// MessagePack with field names, so fields can be added or reordered like in JSON
+pub struct MessagePackCodec;
+
+impl CacheCodec for MessagePackCodec {
+    const TAG: Option<u8> = Some(MESSAGE_PACK_TAG);
+
+    fn encode_into<T: Serialize>(value: &T, buf: &mut Vec<u8>) -> Result<(), CodecError> {
+        Ok(rmp_serde::encode::write_named(buf, value)?)
+    }
+
+    fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, CodecError> {
+        Ok(rmp_serde::from_slice(bytes)?)
+    }
+}
+
+/// Bincode, the most compact and fastest, but any change to the type needs a `CacheKey::VERSION` bump
+pub struct BincodeCodec;
+
+impl CacheCodec for BincodeCodec {
+    const TAG: Option<u8> = Some(BINCODE_TAG);
+
+    fn encode_into<T: Serialize>(value: &T, buf: &mut Vec<u8>) -> Result<(), CodecError> {
+        bincode::serde::encode_into_std_write(value, buf, bincode::config::standard())?;
+        Ok(())
+    }
+
+    fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, CodecError> {
+        let (value, _) = bincode::serde::decode_from_slice(bytes, bincode::config::standard())?;
+        Ok(value)
+    }
+}
+
+/// Codec selected in the cache configuration
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CodecKind {
+    Json,
+    #[serde(alias = "msgpack")]
+    MessagePack,
+    Bincode,
+}
+
+impl CodecKind {
+    /// Encodes a value with this codec, tag included
+    pub fn encode<T: Serialize>(self, value: &T) -> Result<Vec<u8>, CodecError> {
+        match self {
+            CodecKind::Json => encode_with::<JsonCodec, T>(value),
+            CodecKind::MessagePack => encode_with::<MessagePackCodec, T>(value),
+            CodecKind::Bincode => encode_with::<BincodeCodec, T>(value),
+        }
+    }
+}
+
+fn encode_with<C: CacheCodec, T: Serialize>(value: &T) -> Result<Vec<u8>, CodecError> {
+    let mut buf = Vec::new();
+    buf.extend(C::TAG);
+    C::encode_into(value, &mut buf)?;
+    Ok(buf)
+}
+
+/// Codec a value was written with, judged by its tag byte
+pub fn detect(bytes: &[u8]) -> CodecKind {
+    match bytes.first() {
+        Some(&MESSAGE_PACK_TAG) => CodecKind::MessagePack,
+        Some(&BINCODE_TAG) => CodecKind::Bincode,
+        _ => CodecKind::Json,
+    }
+}
+
+/// Decodes a value written by any codec, picked by its tag byte
+pub fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, CodecError> {
+    match bytes.first() {
+        Some(&MESSAGE_PACK_TAG) => MessagePackCodec::decode(&bytes[1..]),
+        Some(&BINCODE_TAG) => BincodeCodec::decode(&bytes[1..]),
+        _ => JsonCodec::decode(bytes),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Entry {
+        fresh_until: i64,
+        data: Vec<String>,
+        validators: Option<String>,
+    }
+
+    fn entry() -> Entry {
+        Entry {
+            fresh_until: 1_700_000_000_000,
+            data: vec!["Leanne Graham".to_string(), "Ervin Howell".to_string()],
+            validators: Some("\"v1\"".to_string()),
+        }
+    }
+
+    #[test]
+    fn every_codec_round_trips() {
+        for kind in [CodecKind::Json, CodecKind::MessagePack, CodecKind::Bincode] {
+            let encoded = kind.encode(&entry()).unwrap();
+
+            assert_eq!(detect(&encoded), kind);
+            assert_eq!(decode::<Entry>(&encoded).unwrap(), entry());
+        }
+    }
+
+    #[test]
+    fn only_json_is_written_without_a_tag() {
+        assert_eq!(CodecKind::Json.encode(&entry()).unwrap(), serde_json::to_vec(&entry()).unwrap());
+        assert_eq!(CodecKind::MessagePack.encode(&entry()).unwrap()[0], MESSAGE_PACK_TAG);
+        assert_eq!(CodecKind::Bincode.encode(&entry()).unwrap()[0], BINCODE_TAG);
+    }
+
+    #[test]
+    fn untagged_values_are_detected_as_json() {
+        assert_eq!(detect(b"{\"id\":1}"), CodecKind::Json);
+        assert_eq!(detect(b"[]"), CodecKind::Json);
+        assert_eq!(detect(b""), CodecKind::Json);
+    }
+
+    #[test]
+    fn values_of_another_shape_fail_to_decode() {
+        let encoded = CodecKind::Bincode.encode(&"not an entry").unwrap();
+
+        assert!(decode::<Entry>(&encoded).is_err());
+        assert!(decode::<Entry>(b"{\"id\":1}").is_err());
+    }
+
+    #[test]
+    fn codec_names_deserialize_with_the_msgpack_alias() {
+        let kinds: Vec<CodecKind> = serde_json::from_str("[\"json\", \"messagepack\", \"msgpack\", \"bincode\"]").unwrap();
+
+        assert_eq!(kinds, [CodecKind::Json, CodecKind::MessagePack, CodecKind::MessagePack, CodecKind::Bincode]);
+    }
+}
@@ -0,0 +1,132 @@
+use std::io::{self, Read, Write};
+
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use metrics::counter;
+
+use crate::config::{CompressionAlgorithm, CompressionConfig};
+
+/// First byte of a zstd-compressed value. Uncompressed values are JSON, the
+/// "not found" marker or start with a codec tag, never with these bytes.
+const ZSTD_HEADER: u8 = 0x01;
+
+/// First byte of a gzip-compressed value
+const GZIP_HEADER: u8 = 0x02;
+
+/// Encodes a value for Redis: compressed behind a header byte when it is at
+/// least `threshold_bytes` and compression actually shrinks it, plain otherwise.
+pub fn encode(value: Vec<u8>, config: &CompressionConfig) -> Vec<u8> {
+    if !config.enabled || value.len() < config.threshold_bytes {
+        return value;
+    }
+
+    let (header, compressed, label) = match config.algorithm {
+        CompressionAlgorithm::Zstd => (ZSTD_HEADER, zstd::bulk::compress(&value, config.level as i32), "zstd"),
+        CompressionAlgorithm::Gzip => (GZIP_HEADER, gzip(&value, config.level), "gzip"),
+    };
+
+    match compressed {
+        Ok(compressed) if compressed.len() + 1 < value.len() => {
+            counter!("cache_compressed_writes_total", "algorithm" => label).increment(1);
+            counter!("cache_compression_bytes_saved_total", "algorithm" => label)
+                .increment((value.len() - compressed.len() - 1) as u64);
+
+            let mut encoded = Vec::with_capacity(compressed.len() + 1);
+            encoded.push(header);
+            encoded.extend_from_slice(&compressed);
+            encoded
+        }
+        _ => value,
+    }
+}
+
+/// Whether a value read from Redis was stored compressed
+pub fn is_compressed(bytes: &[u8]) -> bool {
+    matches!(bytes.first(), Some(&ZSTD_HEADER) | Some(&GZIP_HEADER))
+}
+
+/// Decodes a value read from Redis, whether it was stored compressed or not
+pub fn decode(bytes: Vec<u8>) -> io::Result<Vec<u8>> {
+    let plain = match bytes.first() {
+        Some(&ZSTD_HEADER) => zstd::stream::decode_all(&bytes[1..])?,
+        Some(&GZIP_HEADER) => {
+            let mut plain = Vec::new();
+            GzDecoder::new(&bytes[1..]).read_to_end(&mut plain)?;
+            plain
+        }
+        _ => bytes,
+    };
+    Ok(plain)
+}
+
+fn gzip(data: &[u8], level: u32) -> io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::new(level));
+    encoder.write_all(data)?;
+    encoder.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(algorithm: CompressionAlgorithm) -> CompressionConfig {
+        CompressionConfig { algorithm, ..Default::default() }
+    }
+
+    /// A JSON value compressible well past the default threshold
+    fn large_value() -> Vec<u8> {
+        format!("[{}]", vec!["{\"name\":\"Leanne Graham\"}"; 200].join(",")).into_bytes()
+    }
+
+    #[test]
+    fn every_algorithm_round_trips() {
+        for (algorithm, header) in [(CompressionAlgorithm::Zstd, ZSTD_HEADER), (CompressionAlgorithm::Gzip, GZIP_HEADER)] {
+            let encoded = encode(large_value(), &config(algorithm));
+
+            assert_eq!(encoded[0], header);
+            assert!(is_compressed(&encoded));
+            assert!(encoded.len() < large_value().len());
+            assert_eq!(decode(encoded).unwrap(), large_value());
+        }
+    }
+
+    #[test]
+    fn values_under_the_threshold_are_stored_plain() {
+        let value = b"{\"name\":\"Leanne Graham\"}".to_vec();
+
+        let encoded = encode(value.clone(), &config(CompressionAlgorithm::Zstd));
+
+        assert_eq!(encoded, value);
+        assert!(!is_compressed(&encoded));
+    }
+
+    #[test]
+    fn values_that_do_not_shrink_are_stored_plain() {
+        let value = b"{\"id\":1}".to_vec();
+
+        for algorithm in [CompressionAlgorithm::Zstd, CompressionAlgorithm::Gzip] {
+            let config = CompressionConfig { threshold_bytes: 0, ..config(algorithm) };
+            assert_eq!(encode(value.clone(), &config), value);
+        }
+    }
+
+    #[test]
+    fn disabled_compression_stores_everything_plain() {
+        let config = CompressionConfig { enabled: false, ..Default::default() };
+
+        assert_eq!(encode(large_value(), &config), large_value());
+    }
+
+    #[test]
+    fn legacy_plain_values_decode_unchanged() {
+        for legacy in [large_value(), b"__not_found__".to_vec(), Vec::new()] {
+            assert!(!is_compressed(&legacy));
+            assert_eq!(decode(legacy.clone()).unwrap(), legacy);
+        }
+    }
+
+    #[test]
+    fn corrupt_compressed_values_fail_to_decode() {
+        assert!(decode(vec![ZSTD_HEADER, 1, 2, 3]).is_err());
+        assert!(decode(vec![GZIP_HEADER, 1, 2, 3]).is_err());
+    }
+}
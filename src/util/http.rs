@@ -0,0 +1,110 @@
+use std::time::Instant;
+
+use metrics::counter;
+use reqwest::{
+    header::{IF_MODIFIED_SINCE, IF_NONE_MATCH},
+    Client, Error as ReqwestError, Request, RequestBuilder, Response,
+};
+
+use crate::util::{cache::Validators, metrics::record_upstream_request, retry::RetryPolicy};
+
+/// Extension for outbound requests made with the shared reqwest client
+pub trait RequestBuilderExt {
+    /// Makes the request conditional on the validators of a cached value, if it has any
+    fn conditional(self, validators: &Validators) -> RequestBuilder;
+
+    /// Sends the request and records its latency per upstream host
+    #[allow(dead_code)]
+    async fn send_instrumented(self) -> Result<Response, ReqwestError>;
+
+    /// Like `send_instrumented`, retrying an idempotent request as `policy` allows
+    /// within the policy's deadline. The last response or error is returned once
+    /// retries are exhausted.
+    async fn send_with_retry(self, policy: &RetryPolicy) -> Result<Response, ReqwestError>;
+}
+
+impl RequestBuilderExt for RequestBuilder {
+    fn conditional(self, validators: &Validators) -> RequestBuilder {
+        let mut builder = self;
+        if let Some(etag) = &validators.etag {
+            builder = builder.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &validators.last_modified {
+            builder = builder.header(IF_MODIFIED_SINCE, last_modified);
+        }
+        builder
+    }
+
+    async fn send_instrumented(self) -> Result<Response, ReqwestError> {
+        let (client, request) = self.build_split();
+        execute_instrumented(&client, request?).await
+    }
+
+    async fn send_with_retry(self, policy: &RetryPolicy) -> Result<Response, ReqwestError> {
+        let (client, request) = self.build_split();
+        let mut request = request?;
+        // Requests with a streaming body can't be replayed
+        let replayable = request.method().is_idempotent() && request.try_clone().is_some();
+        let deadline = Instant::now() + policy.deadline();
+        policy.deposit();
+
+        let mut attempt = 1;
+        loop {
+            let next = if replayable && attempt < policy.max_attempts() {
+                request.try_clone()
+            } else {
+                None
+            };
+            // Each attempt only gets what is left of the deadline
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let timeout = request.timeout().map_or(remaining, |timeout| remaining.min(*timeout));
+            *request.timeout_mut() = Some(timeout);
+
+            let host = host_of(&request);
+            let result = execute_instrumented(&client, request).await;
+
+            let Some(next) = next else {
+                return result;
+            };
+            let Some((reason, delay)) = policy.classify(&result, attempt) else {
+                return result;
+            };
+            if Instant::now() + delay >= deadline {
+                counter!("http_client_retry_deadline_exceeded_total", "host" => host).increment(1);
+                return result;
+            }
+            if !policy.withdraw() {
+                counter!("http_client_retry_budget_exhausted_total", "host" => host).increment(1);
+                return result;
+            }
+            counter!("http_client_retries_total", "host" => host, "reason" => reason).increment(1);
+
+            // Hand the connection back to the pool before waiting
+            drop(result);
+            tokio::time::sleep(delay).await;
+            request = next;
+            attempt += 1;
+        }
+    }
+}
+
+/// Executes one attempt and records its status and latency
+async fn execute_instrumented(client: &Client, request: Request) -> Result<Response, ReqwestError> {
+    let host = host_of(&request);
+
+    let start_time = Instant::now();
+    let result = client.execute(request).await;
+
+    let status = match &result {
+        Ok(response) => response.status().as_u16().to_string(),
+        Err(e) if e.is_timeout() => "timeout".to_string(),
+        Err(_) => "error".to_string(),
+    };
+    record_upstream_request(host, status, start_time.elapsed());
+
+    result
+}
+
+fn host_of(request: &Request) -> String {
+    request.url().host_str().unwrap_or("unknown").to_string()
+}
@@ -0,0 +1,149 @@
+use std::time::Duration;
+
+use bb8_redis::redis::{self, AsyncCommands};
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+use crate::util::{cache::MemoryCache, cache_key::glob_match, redis_pool::RedisPool, shutdown::Shutdown};
+
+/// What an invalidation message evicts from the in-memory tier
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Invalidation {
+    Key { key: String },
+    Pattern { pattern: String },
+}
+
+impl Invalidation {
+    /// Evicts the affected entries from a Moka cache
+    pub async fn apply(&self, moka_cache: &MemoryCache) {
+        match self {
+            Invalidation::Key { key } => moka_cache.invalidate(key).await,
+            Invalidation::Pattern { pattern } => {
+                invalidate_matching(moka_cache, pattern).await;
+            }
+        }
+    }
+}
+
+/// Evicts every Moka entry whose key matches `pattern`, returning how many were evicted
+pub async fn invalidate_matching(moka_cache: &MemoryCache, pattern: &str) -> usize {
+    let keys: Vec<_> = moka_cache
+        .iter()
+        .map(|(key, _)| key)
+        .filter(|key| glob_match(pattern, key))
+        .collect();
+    for key in &keys {
+        moka_cache.invalidate(&**key).await;
+    }
+    keys.len()
+}
+
+#[derive(Serialize, Deserialize)]
+struct InvalidationMessage {
+    origin: String, // Publishing instance, which has already updated its own Moka cache
+    #[serde(flatten)]
+    invalidation: Invalidation,
+}
+
+/// Keeps the Moka tier of every instance consistent with Redis.
+///
+/// Writes and deletes are published on a Redis channel; every instance
+/// subscribes to it and evicts the affected keys from its own Moka cache.
+#[derive(Clone)]
+pub struct Invalidator {
+    redis_pool: RedisPool,
+    channel: String,
+    origin: String,
+}
+
+impl Invalidator {
+    pub fn new(redis_pool: RedisPool, channel: String) -> Self {
+        Self {
+            redis_pool,
+            channel,
+            origin: Uuid::new_v4().to_string(),
+        }
+    }
+
+    /// Tells the other instances to evict from their Moka cache; best effort while Redis is degraded
+    pub async fn publish(&self, invalidation: Invalidation) {
+        let message = InvalidationMessage {
+            origin: self.origin.clone(),
+            invalidation,
+        };
+        let Ok(payload) = serde_json::to_string(&message) else {
+            return;
+        };
+
+        if let Some(mut conn) = self.redis_pool.get().await {
+            if let Err(e) = conn.publish::<_, _, ()>(&self.channel, payload).await {
+                self.redis_pool.report_error(&e);
+            }
+        }
+    }
+
+    /// Subscribes to the invalidation channel and keeps resubscribing after connection loss.
+    ///
+    /// Messages published while disconnected are lost, so the whole Moka cache
+    /// is cleared after every reconnect.
+    pub fn spawn_subscriber(
+        &self,
+        moka_cache: MemoryCache,
+        reconnect_interval: Duration,
+        shutdown: &Shutdown,
+    ) {
+        let invalidator = self.clone();
+        let stop = shutdown.clone();
+        shutdown.spawn(async move {
+            let mut connected_before = false;
+            // Only evicts from Moka, so it can stop at any point
+            let resubscribe = async {
+                loop {
+                    match invalidator.subscribe(&moka_cache, &mut connected_before).await {
+                        Ok(()) => warn!("Cache invalidation subscription closed, reconnecting"),
+                        Err(e) => debug!("Cache invalidation subscription failed: {}", e),
+                    }
+                    tokio::time::sleep(reconnect_interval).await;
+                }
+            };
+            tokio::select! {
+                _ = resubscribe => {}
+                _ = stop.stopping() => {}
+            }
+        });
+    }
+
+    async fn subscribe(
+        &self,
+        moka_cache: &MemoryCache,
+        connected_before: &mut bool,
+    ) -> redis::RedisResult<()> {
+        let mut pubsub = self.redis_pool.pubsub().await?;
+        pubsub.subscribe(&self.channel).await?;
+
+        if *connected_before {
+            moka_cache.invalidate_all();
+            info!("Resubscribed to cache invalidations, cleared in-memory cache");
+        } else {
+            info!("Subscribed to cache invalidations on '{}'", self.channel);
+        }
+        *connected_before = true;
+
+        let mut messages = pubsub.into_on_message();
+        while let Some(message) = messages.next().await {
+            let Ok(payload) = message.get_payload::<String>() else {
+                continue;
+            };
+            match serde_json::from_str::<InvalidationMessage>(&payload) {
+                Ok(message) if message.origin == self.origin => {}
+                Ok(message) => message.invalidation.apply(moka_cache).await,
+                Err(e) => warn!("Ignoring malformed cache invalidation message: {}", e),
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,68 @@
+use std::time::Duration;
+
+use metrics::{counter, gauge, histogram};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+
+use crate::util::{redis_pool::RedisPool, shutdown::Shutdown};
+
+/// Histogram buckets for latencies, in seconds
+const LATENCY_BUCKETS: &[f64] = &[
+    0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0,
+];
+
+/// How often histograms are drained into their Prometheus representation
+const UPKEEP_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Installs the global Prometheus recorder and schedules its upkeep
+pub fn install(shutdown: &Shutdown) -> PrometheusHandle {
+    let handle = PrometheusBuilder::new()
+        .set_buckets_for_metric(Matcher::Suffix("_seconds".to_string()), LATENCY_BUCKETS)
+        .expect("Invalid histogram buckets")
+        .install_recorder()
+        .expect("Failed to install Prometheus recorder");
+
+    let upkeep_handle = handle.clone();
+    let stop = shutdown.clone();
+    shutdown.spawn(async move {
+        let mut ticker = tokio::time::interval(UPKEEP_INTERVAL);
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => upkeep_handle.run_upkeep(),
+                _ = stop.stopping() => return,
+            }
+        }
+    });
+
+    handle
+}
+
+/// Records a cache lookup for a tier (`moka`, `redis`, `negative`, `upstream`)
+pub fn record_cache_lookup(tier: &'static str, result: &'static str) {
+    counter!("cache_lookups_total", "tier" => tier, "result" => result).increment(1);
+}
+
+/// Records an outbound HTTP request to an upstream host
+pub fn record_upstream_request(host: String, status: String, elapsed: Duration) {
+    counter!("http_client_requests_total", "host" => host.clone(), "status" => status).increment(1);
+    histogram!("http_client_request_duration_seconds", "host" => host).record(elapsed.as_secs_f64());
+}
+
+/// Publishes the current bb8 pool state as gauges, called on every scrape
+pub fn record_pool_state(redis_pool: &RedisPool) {
+    let state = redis_pool.pool().state();
+    let statistics = state.statistics;
+    let pool = redis_pool.role();
+
+    gauge!("redis_pool_connections", "pool" => pool).set(state.connections as f64);
+    gauge!("redis_pool_idle_connections", "pool" => pool).set(state.idle_connections as f64);
+    gauge!("redis_pool_degraded", "pool" => pool).set(if redis_pool.is_degraded() { 1.0 } else { 0.0 });
+    counter!("redis_pool_gets_total", "pool" => pool, "kind" => "direct").absolute(statistics.get_direct);
+    counter!("redis_pool_gets_total", "pool" => pool, "kind" => "waited").absolute(statistics.get_waited);
+    counter!("redis_pool_gets_total", "pool" => pool, "kind" => "timed_out").absolute(statistics.get_timed_out);
+    gauge!("redis_pool_wait_seconds_total", "pool" => pool).set(statistics.get_wait_time.as_secs_f64());
+    counter!("redis_pool_connections_created_total", "pool" => pool).absolute(statistics.connections_created);
+
+    if let Some(replicas) = redis_pool.replicas() {
+        record_pool_state(replicas);
+    }
+}
@@ -1 +1,19 @@
+pub mod build_info;
 pub mod cache;
+pub mod cache_backend;
+pub mod cache_context;
+pub mod cache_key;
+pub mod circuit_breaker;
+pub mod codec;
+pub mod compression;
+pub mod http;
+pub mod invalidation;
+pub mod metrics;
+pub mod rate_limit;
+pub mod redis_connection;
+pub mod redis_pool;
+pub mod refresh_lock;
+pub mod retry;
+pub mod shutdown;
+pub mod signing;
+pub mod single_flight;
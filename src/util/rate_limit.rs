@@ -0,0 +1,87 @@
+use std::sync::LazyLock;
+
+use bb8_redis::redis::Script;
+use tracing::warn;
+
+use crate::util::redis_pool::RedisPool;
+
+/// Token bucket refilled continuously at `capacity / window`, evaluated
+/// atomically in Redis so every replica shares the same bucket. Redis `TIME`
+/// is used as the clock to avoid skew between instances.
+///
+/// Returns `{allowed, remaining, reset_ms, retry_after_ms}`.
+static TOKEN_BUCKET: LazyLock<Script> = LazyLock::new(|| {
+    Script::new(
+        r#"
+        local capacity = tonumber(ARGV[1])
+        local window_ms = tonumber(ARGV[2])
+        local rate = capacity / window_ms
+
+        local time = redis.call('TIME')
+        local now = tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+
+        local state = redis.call('HMGET', KEYS[1], 'tokens', 'ts')
+        local tokens = tonumber(state[1]) or capacity
+        local ts = tonumber(state[2]) or now
+        tokens = math.min(capacity, tokens + math.max(0, now - ts) * rate)
+
+        local allowed = 0
+        local retry_after_ms = 0
+        if tokens >= 1 then
+            tokens = tokens - 1
+            allowed = 1
+        else
+            retry_after_ms = math.ceil((1 - tokens) / rate)
+        end
+
+        redis.call('HSET', KEYS[1], 'tokens', tostring(tokens), 'ts', now)
+        redis.call('PEXPIRE', KEYS[1], window_ms)
+
+        local reset_ms = math.ceil((capacity - tokens) / rate)
+        return {allowed, math.floor(tokens), reset_ms, retry_after_ms}
+        "#,
+    )
+});
+
+/// Outcome of a rate limit check
+pub struct RateLimitDecision {
+    pub allowed: bool,
+    pub limit: u32,
+    pub remaining: u32,
+    /// Seconds until the bucket is full again
+    pub reset_secs: u64,
+    /// Seconds until the next request would be allowed, 0 when allowed
+    pub retry_after_secs: u64,
+}
+
+/// Takes one token from the bucket stored under `key`.
+///
+/// Returns `None` when Redis is unavailable, leaving the fail-open/closed choice to the caller.
+pub async fn check(
+    redis_pool: &RedisPool,
+    key: &str,
+    limit: u32,
+    window_secs: u64,
+) -> Option<RateLimitDecision> {
+    let mut conn = redis_pool.get().await?;
+
+    let (allowed, remaining, reset_ms, retry_after_ms): (u8, u32, u64, u64) = TOKEN_BUCKET
+        .key(key)
+        .arg(limit)
+        .arg(window_secs * 1000)
+        .invoke_async(&mut *conn)
+        .await
+        .inspect_err(|e| {
+            warn!("Rate limit check failed: {}", e);
+            redis_pool.report_error(e);
+        })
+        .ok()?;
+
+    Some(RateLimitDecision {
+        allowed: allowed == 1,
+        limit,
+        remaining,
+        reset_secs: reset_ms.div_ceil(1000),
+        retry_after_secs: retry_after_ms.div_ceil(1000),
+    })
+}
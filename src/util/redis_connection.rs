@@ -0,0 +1,572 @@
+use std::{
+    fmt,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use bb8_redis::{
+    bb8::ManageConnection,
+    redis::{
+        self,
+        aio::{ConnectionLike, MultiplexedConnection, PubSub},
+        cluster::ClusterClient,
+        cluster_async::ClusterConnection,
+        cluster_routing::{RoutingInfo, SingleNodeRoutingInfo},
+        sentinel::{Sentinel, SentinelNodeConnectionInfo},
+        Client, Cmd, ConnectionAddr, ErrorKind, IntoConnectionInfo, Pipeline, RedisError, RedisFuture,
+        RedisResult, TlsMode, Value,
+    },
+};
+use tokio::sync::Mutex;
+
+use crate::config::{RedisConfig, RedisTopology};
+
+/// bb8 connection manager for every supported Redis deployment
+pub struct RedisManager {
+    target: Target,
+}
+
+enum Target {
+    Standalone(Client),
+    Sentinel(SentinelTarget),
+    Cluster {
+        client: ClusterClient,
+        nodes: Vec<String>,
+        /// Handed out to every pool slot, a cluster connection is multiplexed and already holds one per node
+        shared: Arc<Mutex<Option<ClusterConnection>>>,
+    },
+    /// Fixed replicas, connected to in turn
+    Replicas {
+        clients: Vec<Client>,
+        next: AtomicUsize,
+    },
+    /// Replicas of the Sentinel primary, picked by the sentinels
+    SentinelReplicas(SentinelTarget),
+}
+
+struct SentinelTarget {
+    sentinel: Arc<Mutex<Sentinel>>,
+    master: String,
+    node_info: SentinelNodeConnectionInfo,
+}
+
+impl SentinelTarget {
+    fn new(config: &RedisConfig) -> RedisResult<Self> {
+        // Data nodes are reached with the credentials, database and TLS mode of `url`
+        let node = config.url.as_str().into_connection_info()?;
+        let tls_mode = match node.addr {
+            ConnectionAddr::TcpTls { insecure: true, .. } => Some(TlsMode::Insecure),
+            ConnectionAddr::TcpTls { .. } => Some(TlsMode::Secure),
+            _ => None,
+        };
+        Ok(Self {
+            sentinel: Arc::new(Mutex::new(Sentinel::build(config.nodes.clone())?)),
+            master: config.sentinel_master.clone(),
+            node_info: SentinelNodeConnectionInfo {
+                tls_mode,
+                redis_connection_info: Some(node.redis),
+            },
+        })
+    }
+}
+
+impl RedisManager {
+    pub fn new(config: &RedisConfig) -> RedisResult<Self> {
+        let target = match config.topology {
+            RedisTopology::Standalone => Target::Standalone(Client::open(config.url.as_str())?),
+            RedisTopology::Sentinel => Target::Sentinel(SentinelTarget::new(config)?),
+            RedisTopology::Cluster => Target::Cluster {
+                client: ClusterClient::new(config.nodes.clone())?,
+                nodes: config.nodes.clone(),
+                shared: Arc::new(Mutex::new(None)),
+            },
+        };
+        Ok(Self { target })
+    }
+
+    /// Manager for the read replicas in `config.replicas`
+    pub fn replicas(config: &RedisConfig) -> RedisResult<Self> {
+        let target = match config.replicas.urls.as_slice() {
+            [] => Target::SentinelReplicas(SentinelTarget::new(config)?),
+            urls => Target::Replicas {
+                clients: urls.iter().map(|url| Client::open(url.as_str())).collect::<RedisResult<_>>()?,
+                next: AtomicUsize::new(0),
+            },
+        };
+        Ok(Self { target })
+    }
+
+    /// Client for the node to connect to next; under Sentinel resolved on every call
+    async fn node(&self) -> RedisResult<Client> {
+        match &self.target {
+            Target::Standalone(client) => Ok(client.clone()),
+            Target::Sentinel(SentinelTarget { sentinel, master, node_info }) => {
+                sentinel.lock().await.async_master_for(master, Some(node_info)).await
+            }
+            Target::Cluster { nodes, .. } => {
+                Client::open(nodes.first().map(String::as_str).unwrap_or_default())
+            }
+            Target::Replicas { clients, next } => {
+                Ok(clients[next.fetch_add(1, Ordering::Relaxed) % clients.len()].clone())
+            }
+            Target::SentinelReplicas(SentinelTarget { sentinel, master, node_info }) => {
+                sentinel.lock().await.async_replica_for(master, Some(node_info)).await
+            }
+        }
+    }
+
+    /// Dedicated pub/sub connection. In a cluster, messages published on any
+    /// node reach subscribers on every node, so the first reachable seed is used.
+    pub async fn pubsub(&self) -> RedisResult<PubSub> {
+        let Target::Cluster { nodes, .. } = &self.target else {
+            return self.node().await?.get_async_pubsub().await;
+        };
+
+        let mut last_error = None;
+        for node in nodes {
+            match Client::open(node.as_str())?.get_async_pubsub().await {
+                Ok(pubsub) => return Ok(pubsub),
+                Err(e) => last_error = Some(e),
+            }
+        }
+        Err(last_error.unwrap_or_else(|| (ErrorKind::ClientError, "no cluster nodes configured").into()))
+    }
+}
+
+impl ManageConnection for RedisManager {
+    type Connection = RedisConnection;
+    type Error = RedisError;
+
+    async fn connect(&self) -> Result<Self::Connection, Self::Error> {
+        match &self.target {
+            Target::Cluster { client, shared, .. } => {
+                let mut shared = shared.lock().await;
+                if let Some(conn) = shared.as_ref() {
+                    return Ok(RedisConnection::Cluster(conn.clone()));
+                }
+                let conn = client.get_async_connection().await?;
+                *shared = Some(conn.clone());
+                Ok(RedisConnection::Cluster(conn))
+            }
+            _ => Ok(RedisConnection::Single(self.node().await?.get_multiplexed_async_connection().await?)),
+        }
+    }
+
+    async fn is_valid(&self, conn: &mut Self::Connection) -> Result<(), Self::Error> {
+        // After a failover the old primary answers PING as a replica, ROLE catches that
+        if let Target::Sentinel(_) = self.target {
+            let role: Vec<Value> = redis::cmd("ROLE").query_async(conn).await?;
+            return match role.first().map(redis::from_redis_value::<String>) {
+                Some(Ok(role)) if role == "master" => Ok(()),
+                _ => Err((ErrorKind::ReadOnly, "connection is no longer to the primary").into()),
+            };
+        }
+
+        let result = match redis::cmd("PING").query_async::<String>(conn).await {
+            Ok(pong) if pong == "PONG" => Ok(()),
+            Ok(_) => Err((ErrorKind::ResponseError, "ping request").into()),
+            Err(e) => Err(e),
+        };
+        // The next connect opens a fresh cluster connection instead of cloning the broken one
+        if let (Err(_), Target::Cluster { shared, .. }) = (&result, &self.target) {
+            shared.lock().await.take();
+        }
+        result
+    }
+
+    fn has_broken(&self, _: &mut Self::Connection) -> bool {
+        false
+    }
+}
+
+/// Connection handed out by the pool, to a single node or to the whole cluster
+#[derive(Clone)]
+pub enum RedisConnection {
+    Single(MultiplexedConnection),
+    Cluster(ClusterConnection),
+}
+
+impl ConnectionLike for RedisConnection {
+    fn req_packed_command<'a>(&'a mut self, cmd: &'a Cmd) -> RedisFuture<'a, Value> {
+        match self {
+            RedisConnection::Single(conn) => conn.req_packed_command(cmd),
+            RedisConnection::Cluster(conn) => conn.req_packed_command(cmd),
+        }
+    }
+
+    fn req_packed_commands<'a>(
+        &'a mut self,
+        cmd: &'a Pipeline,
+        offset: usize,
+        count: usize,
+    ) -> RedisFuture<'a, Vec<Value>> {
+        match self {
+            RedisConnection::Single(conn) => conn.req_packed_commands(cmd, offset, count),
+            RedisConnection::Cluster(conn) => conn.req_packed_commands(cmd, offset, count),
+        }
+    }
+
+    fn get_db(&self) -> i64 {
+        match self {
+            RedisConnection::Single(conn) => conn.get_db(),
+            RedisConnection::Cluster(conn) => conn.get_db(),
+        }
+    }
+}
+
+impl RedisConnection {
+    /// One `SCAN MATCH` step over the whole keyspace; in a cluster, primaries are scanned one after another
+    pub async fn scan_match(
+        &mut self,
+        cursor: ScanCursor,
+        pattern: &str,
+        count: usize,
+    ) -> RedisResult<(ScanCursor, Vec<String>)> {
+        let mut scan = redis::cmd("SCAN");
+        scan.arg(cursor.cursor).arg("MATCH").arg(pattern).arg("COUNT").arg(count);
+
+        let conn = match self {
+            RedisConnection::Single(conn) => {
+                let (next, keys): (u64, Vec<String>) = scan.query_async(conn).await?;
+                return Ok((ScanCursor { node: 0, cursor: next }, keys));
+            }
+            RedisConnection::Cluster(conn) => conn,
+        };
+
+        let primaries = cluster_primaries(conn).await?;
+        let Some((host, port)) = primaries.get(cursor.node).cloned() else {
+            return Ok((ScanCursor::START, Vec::new()));
+        };
+        let routing = RoutingInfo::SingleNode(SingleNodeRoutingInfo::ByAddress { host, port });
+        let (next, keys): (u64, Vec<String>) =
+            redis::from_owned_redis_value(conn.route_command(&scan, routing).await?)?;
+
+        let next = match next {
+            0 if cursor.node + 1 < primaries.len() => ScanCursor { node: cursor.node + 1, cursor: 0 },
+            0 => ScanCursor::START,
+            next => ScanCursor { node: cursor.node, cursor: next },
+        };
+        Ok((next, keys))
+    }
+}
+
+/// Addresses of the cluster primaries, in a stable order so scan cursors stay valid between calls
+async fn cluster_primaries(conn: &mut ClusterConnection) -> RedisResult<Vec<(String, u16)>> {
+    // Each entry is [start slot, end slot, [primary host, port, id], replicas...]
+    let slots: Vec<Vec<Value>> = redis::cmd("CLUSTER").arg("SLOTS").query_async(conn).await?;
+    let mut primaries = Vec::new();
+    for range in slots {
+        let Some(primary) = range.get(2) else {
+            continue;
+        };
+        let primary: Vec<Value> = redis::from_redis_value(primary)?;
+        if let [host, port, ..] = primary.as_slice() {
+            primaries.push((redis::from_redis_value(host)?, redis::from_redis_value(port)?));
+        }
+    }
+    primaries.sort();
+    primaries.dedup();
+    Ok(primaries)
+}
+
+/// Position of a key scan, rendered `{cursor}` on a single node and `{node}-{cursor}` in a cluster
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScanCursor {
+    node: usize,
+    cursor: u64,
+}
+
+impl ScanCursor {
+    /// Starts a scan, and is returned once it is complete
+    pub const START: ScanCursor = ScanCursor { node: 0, cursor: 0 };
+
+    pub fn is_complete(&self) -> bool {
+        *self == Self::START
+    }
+}
+
+impl fmt::Display for ScanCursor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.node {
+            0 => write!(f, "{}", self.cursor),
+            node => write!(f, "{}-{}", node, self.cursor),
+        }
+    }
+}
+
+impl FromStr for ScanCursor {
+    type Err = std::num::ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('-') {
+            Some((node, cursor)) => Ok(ScanCursor { node: node.parse()?, cursor: cursor.parse()? }),
+            None => Ok(ScanCursor { node: 0, cursor: s.parse()? }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashSet,
+        net::TcpListener,
+        path::PathBuf,
+        process::{Child, Command, Stdio},
+        time::{Duration, Instant},
+    };
+
+    use super::*;
+
+    #[test]
+    fn scan_cursor_round_trips() {
+        for text in ["0", "17", "2-0", "3-42"] {
+            let cursor: ScanCursor = text.parse().unwrap();
+            assert_eq!(cursor.to_string(), text);
+        }
+        assert_eq!("0".parse::<ScanCursor>().unwrap(), ScanCursor::START);
+        assert_eq!("0-17".parse::<ScanCursor>().unwrap(), ScanCursor { node: 0, cursor: 17 });
+    }
+
+    #[test]
+    fn scan_cursor_rejects_garbage() {
+        for text in ["", "abc", "-1", "1-", "1-2-3", "x-1"] {
+            assert!(text.parse::<ScanCursor>().is_err(), "{:?} parsed", text);
+        }
+    }
+
+    #[test]
+    fn scan_cursor_completes_only_at_start() {
+        assert!(ScanCursor::START.is_complete());
+        assert!(!ScanCursor { node: 0, cursor: 5 }.is_complete());
+        // The next primary's scan starts at cursor 0 but is not the end
+        assert!(!ScanCursor { node: 1, cursor: 0 }.is_complete());
+    }
+
+    /// A `redis-server` process on a free port, killed on drop. The binary is taken
+    /// from `REDIS_SERVER_BIN`, or `redis-server` on the `PATH`.
+    struct RedisServer {
+        port: u16,
+        dir: PathBuf,
+        child: Child,
+    }
+
+    impl RedisServer {
+        fn start(args: &[&str]) -> Self {
+            Self::spawn(None, args)
+        }
+
+        /// Runs a sentinel monitoring `master_port` as `mymaster` with a quorum of 1
+        fn sentinel(master_port: u16) -> Self {
+            let port = free_port();
+            let dir = std::env::temp_dir().join(format!("rust-backend-redis-{}", port));
+            std::fs::create_dir_all(&dir).unwrap();
+            let conf = dir.join("sentinel.conf");
+            std::fs::write(
+                &conf,
+                format!(
+                    "port {}\n\
+                     sentinel monitor mymaster 127.0.0.1 {} 1\n\
+                     sentinel down-after-milliseconds mymaster 1000\n\
+                     sentinel failover-timeout mymaster 5000\n",
+                    port, master_port
+                ),
+            )
+            .unwrap();
+            Self::spawn(Some((port, dir, conf)), &["--sentinel"])
+        }
+
+        fn spawn(sentinel: Option<(u16, PathBuf, PathBuf)>, args: &[&str]) -> Self {
+            let bin = std::env::var("REDIS_SERVER_BIN").unwrap_or_else(|_| "redis-server".to_string());
+            let mut command = Command::new(bin);
+            let (port, dir) = match sentinel {
+                Some((port, dir, conf)) => {
+                    command.arg(conf);
+                    (port, dir)
+                }
+                None => {
+                    let port = free_port();
+                    let dir = std::env::temp_dir().join(format!("rust-backend-redis-{}", port));
+                    std::fs::create_dir_all(&dir).unwrap();
+                    command.args(["--port", &port.to_string(), "--save", "", "--appendonly", "no"]);
+                    (port, dir)
+                }
+            };
+            let child = command
+                .args(args)
+                .current_dir(&dir)
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .spawn()
+                .expect("redis-server should be installed, or REDIS_SERVER_BIN set");
+
+            let server = Self { port, dir, child };
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while std::net::TcpStream::connect(("127.0.0.1", port)).is_err() {
+                assert!(Instant::now() < deadline, "redis-server on port {} did not start", port);
+                std::thread::sleep(Duration::from_millis(50));
+            }
+            server
+        }
+
+        fn url(&self) -> String {
+            format!("redis://127.0.0.1:{}", self.port)
+        }
+
+        async fn conn(&self) -> MultiplexedConnection {
+            Client::open(self.url()).unwrap().get_multiplexed_async_connection().await.unwrap()
+        }
+    }
+
+    impl Drop for RedisServer {
+        fn drop(&mut self) {
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    fn free_port() -> u16 {
+        TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+    }
+
+    /// Polls `check` until it holds, for at most `secs` seconds
+    async fn eventually<F, Fut>(secs: u64, what: &str, mut check: F)
+    where
+        F: FnMut() -> Fut,
+        Fut: std::future::Future<Output = bool>,
+    {
+        let deadline = Instant::now() + Duration::from_secs(secs);
+        while !check().await {
+            assert!(Instant::now() < deadline, "timed out waiting for {}", what);
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        }
+    }
+
+    async fn scan_all(conn: &mut RedisConnection, pattern: &str) -> (HashSet<String>, Vec<ScanCursor>) {
+        let mut keys = HashSet::new();
+        let mut cursors = Vec::new();
+        let mut cursor = ScanCursor::START;
+        loop {
+            let (next, batch) = conn.scan_match(cursor, pattern, 10).await.unwrap();
+            keys.extend(batch);
+            cursor = next;
+            if cursor.is_complete() {
+                return (keys, cursors);
+            }
+            // Cursors are handed to clients as strings between pages
+            cursors.push(cursor);
+            cursor = cursor.to_string().parse().unwrap();
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "needs redis-server"]
+    async fn standalone_scan_walks_every_key() {
+        let server = RedisServer::start(&[]);
+        let config = RedisConfig { url: server.url(), ..RedisConfig::default() };
+        let manager = RedisManager::new(&config).unwrap();
+        let mut conn = manager.connect().await.unwrap();
+        manager.is_valid(&mut conn).await.unwrap();
+
+        for i in 0..250 {
+            redis::cmd("SET").arg(format!("scan:{}", i)).arg(i).exec_async(&mut conn).await.unwrap();
+        }
+        redis::cmd("SET").arg("other").arg(0).exec_async(&mut conn).await.unwrap();
+
+        let (keys, cursors) = scan_all(&mut conn, "scan:*").await;
+        assert_eq!(keys.len(), 250);
+        assert!(cursors.iter().all(|cursor| cursor.node == 0));
+    }
+
+    #[tokio::test]
+    #[ignore = "needs redis-server"]
+    async fn cluster_scan_walks_every_primary() {
+        let servers: Vec<_> = (0..3)
+            .map(|_| RedisServer::start(&["--cluster-enabled", "yes", "--cluster-config-file", "nodes.conf"]))
+            .collect();
+
+        // Split the 16384 slots between the three nodes and introduce them to each other
+        for (i, server) in servers.iter().enumerate() {
+            let mut conn = server.conn().await;
+            let (start, end) = (i * 16384 / 3, (i + 1) * 16384 / 3 - 1);
+            redis::cmd("CLUSTER").arg("ADDSLOTSRANGE").arg(start).arg(end).exec_async(&mut conn).await.unwrap();
+            for other in &servers {
+                redis::cmd("CLUSTER").arg("MEET").arg("127.0.0.1").arg(other.port).exec_async(&mut conn).await.unwrap();
+            }
+        }
+        for server in &servers {
+            let conn = server.conn().await;
+            eventually(30, "the cluster to form", || {
+                let mut conn = conn.clone();
+                async move {
+                    let info: String = redis::cmd("CLUSTER").arg("INFO").query_async(&mut conn).await.unwrap();
+                    info.contains("cluster_state:ok")
+                }
+            })
+            .await;
+        }
+
+        let config = RedisConfig {
+            topology: RedisTopology::Cluster,
+            nodes: servers.iter().map(RedisServer::url).collect(),
+            ..RedisConfig::default()
+        };
+        let manager = RedisManager::new(&config).unwrap();
+        let mut conn = manager.connect().await.unwrap();
+        manager.is_valid(&mut conn).await.unwrap();
+
+        for i in 0..300 {
+            redis::cmd("SET").arg(format!("scan:{}", i)).arg(i).exec_async(&mut conn).await.unwrap();
+        }
+
+        let (keys, cursors) = scan_all(&mut conn, "scan:*").await;
+        assert_eq!(keys.len(), 300);
+        let nodes: HashSet<_> = cursors.iter().map(|cursor| cursor.node).collect();
+        assert_eq!(nodes, HashSet::from([0, 1, 2]));
+    }
+
+    #[tokio::test]
+    #[ignore = "needs redis-server"]
+    async fn sentinel_failover_invalidates_the_old_primary() {
+        let primary = RedisServer::start(&[]);
+        let replica = RedisServer::start(&["--replicaof", "127.0.0.1", &primary.port.to_string()]);
+        let sentinel = RedisServer::sentinel(primary.port);
+
+        let config = RedisConfig {
+            url: "redis://127.0.0.1".to_string(),
+            topology: RedisTopology::Sentinel,
+            nodes: vec![sentinel.url()],
+            sentinel_master: "mymaster".to_string(),
+            ..RedisConfig::default()
+        };
+        let manager = RedisManager::new(&config).unwrap();
+        let mut old = manager.connect().await.unwrap();
+        manager.is_valid(&mut old).await.unwrap();
+
+        // FAILOVER is refused until the sentinel has discovered a synced replica
+        let sentinel_conn = sentinel.conn().await;
+        eventually(30, "the sentinel to accept a failover", || {
+            let mut conn = sentinel_conn.clone();
+            async move {
+                redis::cmd("SENTINEL").arg("FAILOVER").arg("mymaster").exec_async(&mut conn).await.is_ok()
+            }
+        })
+        .await;
+
+        // The old primary still answers PING, only ROLE shows it was demoted
+        eventually(30, "the old primary to be demoted", || {
+            let mut old = old.clone();
+            let manager = &manager;
+            async move { manager.is_valid(&mut old).await.is_err() }
+        })
+        .await;
+
+        let mut new = manager.connect().await.unwrap();
+        manager.is_valid(&mut new).await.unwrap();
+        let port: Vec<String> = redis::cmd("CONFIG").arg("GET").arg("port").query_async(&mut new).await.unwrap();
+        assert_eq!(port[1], replica.port.to_string());
+    }
+}
@@ -0,0 +1,202 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use bb8_redis::{
+    bb8::{Pool, PooledConnection, RunError},
+    redis::{self, aio::PubSub, RedisError},
+};
+use metrics::counter;
+use tracing::{info, warn};
+
+use crate::{
+    config::RedisConfig,
+    util::{redis_connection::RedisManager, shutdown::Shutdown},
+};
+
+/// Redis pool treated as an optional cache tier.
+///
+/// When Redis cannot be reached the pool is marked degraded, callers skip the
+/// Redis tier instead of waiting on connection timeouts, and a background task
+/// keeps probing until the connection is restored.
+///
+/// With replicas configured, cache reads use a second pool that degrades and
+/// recovers on its own; reads fall back to the primary in the meantime.
+#[derive(Clone)]
+pub struct RedisPool {
+    pool: Pool<RedisManager>,
+    max_size: u32,                     // Connections the pool may open, to tell saturation from an outage
+    pubsub: Arc<RedisManager>,
+    degraded: Arc<AtomicBool>,
+    role: &'static str,                // "primary" or "replica", for logs and metrics
+    replicas: Option<Arc<RedisPool>>,  // Read replicas, never set on a replica pool
+}
+
+impl RedisPool {
+    /// Builds the pool without requiring Redis to be up, then probes it once
+    pub async fn connect(config: &RedisConfig) -> Result<Self, RedisError> {
+        let mut redis_pool = Self::build(config, RedisManager::new(config)?, RedisManager::new(config)?, "primary");
+        if config.replicas.enabled {
+            let replicas = Self::build(config, RedisManager::replicas(config)?, RedisManager::replicas(config)?, "replica");
+            if let Err(e) = replicas.ping().await {
+                warn!("Redis replicas are unavailable, reading from the primary: {}", e);
+                replicas.degraded.store(true, Ordering::Relaxed);
+            }
+            redis_pool.replicas = Some(Arc::new(replicas));
+        }
+
+        if let Err(e) = redis_pool.ping().await {
+            warn!("Redis is unavailable, starting in degraded moka-only mode: {}", e);
+            redis_pool.degraded.store(true, Ordering::Relaxed);
+        }
+
+        Ok(redis_pool)
+    }
+
+    fn build(config: &RedisConfig, manager: RedisManager, pubsub: RedisManager, role: &'static str) -> Self {
+        let pool = Pool::builder()
+            .max_size(config.pool_max_size)
+            .min_idle(config.pool_min_idle)
+            .max_lifetime(None)
+            .connection_timeout(Duration::from_millis(config.connection_timeout_ms))
+            .idle_timeout(Some(Duration::from_secs(config.idle_timeout_secs)))
+            .build_unchecked(manager);
+
+        Self {
+            pool,
+            max_size: config.pool_max_size,
+            pubsub: Arc::new(pubsub),
+            degraded: Arc::new(AtomicBool::new(false)),
+            role,
+            replicas: None,
+        }
+    }
+
+    /// The underlying bb8 pool
+    pub fn pool(&self) -> &Pool<RedisManager> {
+        &self.pool
+    }
+
+    /// `primary` or `replica`
+    pub fn role(&self) -> &'static str {
+        self.role
+    }
+
+    /// The read replica pool, if configured
+    pub fn replicas(&self) -> Option<&RedisPool> {
+        self.replicas.as_deref()
+    }
+
+    /// Whether the Redis tier is currently being skipped
+    pub fn is_degraded(&self) -> bool {
+        self.degraded.load(Ordering::Relaxed)
+    }
+
+    /// Returns a pooled connection, or `None` if Redis is degraded, unreachable or the pool is exhausted
+    pub async fn get(&self) -> Option<PooledConnection<'_, RedisManager>> {
+        if self.is_degraded() {
+            return None;
+        }
+
+        match self.pool.get().await {
+            Ok(conn) => Some(conn),
+            Err(RunError::User(e)) => {
+                self.report_error(&e);
+                None
+            }
+            Err(RunError::TimedOut) => {
+                // A full pool of busy connections is load, not an outage; only a pool
+                // that could not open a connection within the timeout means Redis is gone
+                if self.pool.state().connections < self.max_size {
+                    self.mark_degraded("timed out opening a connection");
+                } else {
+                    counter!("redis_pool_exhausted_total", "pool" => self.role).increment(1);
+                }
+                None
+            }
+        }
+    }
+
+    /// Returns a connection for cache reads along with the pool it came from:
+    /// a replica if available, else the primary
+    pub async fn get_for_read(&self) -> Option<(PooledConnection<'_, RedisManager>, &RedisPool)> {
+        if let Some(replicas) = &self.replicas {
+            if let Some(conn) = replicas.get().await {
+                counter!("redis_reads_total", "pool" => replicas.role).increment(1);
+                return Some((conn, replicas));
+            }
+            counter!("redis_replica_fallbacks_total").increment(1);
+        }
+
+        let conn = self.get().await?;
+        counter!("redis_reads_total", "pool" => self.role).increment(1);
+        Some((conn, self))
+    }
+
+    /// Opens a dedicated pub/sub connection, to the current primary under Sentinel
+    pub async fn pubsub(&self) -> Result<PubSub, RedisError> {
+        self.pubsub.pubsub().await
+    }
+
+    /// Marks the pool degraded if a command failed because the connection is gone
+    pub fn report_error(&self, error: &RedisError) {
+        if error.is_io_error()
+            || error.is_connection_refusal()
+            || error.is_connection_dropped()
+            || error.is_timeout()
+        {
+            self.mark_degraded(&error.to_string());
+        }
+    }
+
+    /// Spawns a task that probes Redis while degraded and restores the tier once it answers
+    pub fn spawn_reconnect(&self, interval: Duration, shutdown: &Shutdown) {
+        if let Some(replicas) = &self.replicas {
+            replicas.spawn_reconnect(interval, shutdown);
+        }
+
+        let redis_pool = self.clone();
+        let stop = shutdown.clone();
+        shutdown.spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                tokio::select! {
+                    _ = ticker.tick() => {}
+                    _ = stop.stopping() => return,
+                }
+                if !redis_pool.is_degraded() {
+                    continue;
+                }
+                if redis_pool.ping().await.is_ok() {
+                    redis_pool.degraded.store(false, Ordering::Relaxed);
+                    match redis_pool.role {
+                        "replica" => info!("Redis replica connection restored, reading from replicas again"),
+                        _ => info!("Redis connection restored, leaving degraded mode"),
+                    }
+                }
+            }
+        });
+    }
+
+    /// Sends a PING over a pooled connection, bypassing the degraded flag
+    pub async fn ping(&self) -> Result<(), String> {
+        let mut conn = self.pool.get().await.map_err(|e| e.to_string())?;
+        redis::cmd("PING")
+            .query_async::<()>(&mut *conn)
+            .await
+            .map_err(|e| e.to_string())
+    }
+
+    fn mark_degraded(&self, reason: &str) {
+        if !self.degraded.swap(true, Ordering::Relaxed) {
+            match self.role {
+                "replica" => warn!("Redis replicas became unavailable, reading from the primary: {}", reason),
+                _ => warn!("Redis became unavailable, falling back to moka-only mode: {}", reason),
+            }
+        }
+    }
+}
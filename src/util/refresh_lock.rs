@@ -0,0 +1,147 @@
+use std::{sync::LazyLock, time::Duration};
+
+use bb8_redis::redis::{self, AsyncCommands, Script};
+use metrics::counter;
+use tracing::debug;
+
+use crate::util::redis_pool::RedisPool;
+
+/// Global counter handing out fencing tokens, strictly increasing across replicas
+const FENCE_COUNTER_KEY: &str = "refresh_lock:fence";
+
+/// Sets `KEYS[2]` only while `KEYS[1]` still holds our token, so a holder whose
+/// lock expired mid-fetch cannot overwrite a newer value.
+///
+/// Returns 1 if written, 0 if the lock was lost.
+static FENCED_SET: LazyLock<Script> = LazyLock::new(|| {
+    Script::new(
+        r#"
+        if redis.call('GET', KEYS[1]) ~= ARGV[1] then
+            return 0
+        end
+        redis.call('SET', KEYS[2], ARGV[2], 'PX', ARGV[3])
+        return 1
+        "#,
+    )
+});
+
+/// Deletes the lock only if it still holds our token
+static RELEASE: LazyLock<Script> = LazyLock::new(|| {
+    Script::new(
+        r#"
+        if redis.call('GET', KEYS[1]) == ARGV[1] then
+            return redis.call('DEL', KEYS[1])
+        end
+        return 0
+        "#,
+    )
+});
+
+/// Outcome of trying to take the refresh lock for a key
+pub enum Acquire {
+    /// This replica holds the lock under the given fencing token
+    Acquired(u64),
+    /// Another replica is fetching the key
+    Held,
+    /// Redis is unavailable, callers fetch without a lock
+    Unavailable,
+}
+
+/// Lock key for a cache key. The hash tag puts it in the same cluster slot as
+/// the cache key itself, which the fenced write relies on.
+fn lock_key(key: &str) -> String {
+    format!("refresh_lock:{{{}}}", key)
+}
+
+/// Takes the refresh lock for `key` with `SET NX PX` and a fresh fencing token
+pub async fn acquire(redis_pool: &RedisPool, key: &str, ttl: Duration) -> Acquire {
+    let Some(mut conn) = redis_pool.get().await else {
+        return Acquire::Unavailable;
+    };
+
+    let token: u64 = match conn.incr(FENCE_COUNTER_KEY, 1).await {
+        Ok(token) => token,
+        Err(e) => {
+            redis_pool.report_error(&e);
+            return Acquire::Unavailable;
+        }
+    };
+
+    let result: redis::RedisResult<Option<String>> = redis::cmd("SET")
+        .arg(lock_key(key))
+        .arg(token)
+        .arg("NX")
+        .arg("PX")
+        .arg(ttl.as_millis() as u64)
+        .query_async(&mut *conn)
+        .await;
+
+    let outcome = match result {
+        Ok(Some(_)) => Acquire::Acquired(token),
+        Ok(None) => Acquire::Held,
+        Err(e) => {
+            redis_pool.report_error(&e);
+            Acquire::Unavailable
+        }
+    };
+
+    let label = match outcome {
+        Acquire::Acquired(_) => "acquired",
+        Acquire::Held => "held",
+        Acquire::Unavailable => "unavailable",
+    };
+    counter!("cache_refresh_locks_total", "outcome" => label).increment(1);
+
+    outcome
+}
+
+/// Writes `value` under `key` with `SET PX` if the lock still holds `token`.
+///
+/// Returns `false` when the lock expired and was taken over, or Redis failed.
+pub async fn fenced_set(
+    redis_pool: &RedisPool,
+    key: &str,
+    token: u64,
+    value: &[u8],
+    ttl: Duration,
+) -> bool {
+    let Some(mut conn) = redis_pool.get().await else {
+        return false;
+    };
+
+    let written: redis::RedisResult<u8> = FENCED_SET
+        .key(lock_key(key))
+        .key(key)
+        .arg(token)
+        .arg(value)
+        .arg(ttl.as_millis() as u64)
+        .invoke_async(&mut *conn)
+        .await;
+
+    match written {
+        Ok(1) => true,
+        Ok(_) => {
+            debug!("Refresh lock for '{}' was lost, discarding write with token {}", key, token);
+            counter!("cache_refresh_locks_total", "outcome" => "lost").increment(1);
+            false
+        }
+        Err(e) => {
+            redis_pool.report_error(&e);
+            false
+        }
+    }
+}
+
+/// Releases the lock if it still holds `token`
+pub async fn release(redis_pool: &RedisPool, key: &str, token: u64) {
+    if let Some(mut conn) = redis_pool.get().await {
+        if let Err(e) = RELEASE
+            .key(lock_key(key))
+            .arg(token)
+            .invoke_async::<()>(&mut *conn)
+            .await
+        {
+            redis_pool.report_error(&e);
+        }
+    }
+}
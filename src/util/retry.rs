@@ -0,0 +1,260 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use reqwest::{header::RETRY_AFTER, Error as ReqwestError, Response, StatusCode};
+
+use crate::config::{HttpClientConfig, RetryConfig};
+
+/// Retry policy of idempotent upstream requests, shared by every caller.
+///
+/// Retries are paid from a budget that each request tops up by `budget_ratio`,
+/// so a failing upstream sees a bounded amount of extra traffic.
+#[derive(Clone)]
+pub struct RetryPolicy {
+    config: Arc<RetryConfig>,
+    deadline: Duration,      // Time allowed for all attempts and backoffs together
+    budget: Arc<Mutex<f64>>, // Retries currently available
+}
+
+impl RetryPolicy {
+    pub fn new(config: &HttpClientConfig) -> Self {
+        let budget = f64::from(config.retry.budget_burst);
+        Self {
+            config: Arc::new(config.retry.clone()),
+            deadline: Duration::from_secs(config.timeout_secs),
+            budget: Arc::new(Mutex::new(budget)),
+        }
+    }
+
+    /// The client's total timeout, shared by every attempt of a request so
+    /// retries can't stretch it to `max_attempts` times as long
+    pub fn deadline(&self) -> Duration {
+        self.deadline
+    }
+
+    /// Attempts per request, 1 when retries are disabled
+    pub fn max_attempts(&self) -> u32 {
+        if self.config.enabled {
+            self.config.max_attempts
+        } else {
+            1
+        }
+    }
+
+    /// Credits the budget for a new request
+    pub fn deposit(&self) {
+        let mut budget = self.budget.lock().unwrap_or_else(|e| e.into_inner());
+        *budget = (*budget + self.config.budget_ratio).min(f64::from(self.config.budget_burst));
+    }
+
+    /// Takes one retry from the budget, `false` once it is spent
+    pub fn withdraw(&self) -> bool {
+        let mut budget = self.budget.lock().unwrap_or_else(|e| e.into_inner());
+        if *budget < 1.0 {
+            return false;
+        }
+        *budget -= 1.0;
+        true
+    }
+
+    /// Whether the outcome of attempt `attempt` (1-based) is worth retrying, with
+    /// the reason and the delay to wait first
+    pub fn classify(&self, result: &Result<Response, ReqwestError>, attempt: u32) -> Option<(&'static str, Duration)> {
+        let backoff = self.backoff(attempt);
+        let response = match result {
+            Err(e) if e.is_connect() => return Some(("connect", backoff)),
+            Err(e) if e.is_timeout() => return Some(("timeout", backoff)),
+            Err(_) => return None,
+            Ok(response) => response,
+        };
+
+        let reason = match response.status() {
+            StatusCode::TOO_MANY_REQUESTS => "rate_limited",
+            status if status.is_server_error() => "server_error",
+            _ => return None,
+        };
+        match retry_after(response) {
+            Some(wait) if wait > Duration::from_secs(self.config.max_retry_after_secs) => None,
+            Some(wait) => Some((reason, wait.max(backoff))),
+            None => Some((reason, backoff)),
+        }
+    }
+
+    /// Full jitter over an exponential backoff: uniform in `0..=min(max, base * 2^(attempt - 1))`
+    fn backoff(&self, attempt: u32) -> Duration {
+        let ceiling = Duration::from_millis(self.config.base_delay_ms)
+            .saturating_mul(1 << attempt.saturating_sub(1).min(16))
+            .min(Duration::from_millis(self.config.max_delay_ms));
+        ceiling.mul_f64(rand::random_range(0.0..=1.0))
+    }
+}
+
+/// `Retry-After` of a response, given in seconds or as an HTTP date
+fn retry_after(response: &Response) -> Option<Duration> {
+    let value = response.headers().get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    // A date in the past means "now"
+    Some((date.with_timezone(&Utc) - Utc::now()).to_std().unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use axum::{http, routing::get, Router};
+    use reqwest::Client;
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::util::{
+        cache::{Fetched, JsonResponseExt},
+        http::RequestBuilderExt,
+    };
+
+    fn policy(retry: RetryConfig) -> RetryPolicy {
+        RetryPolicy::new(&HttpClientConfig { retry, ..Default::default() })
+    }
+
+    fn response(status: u16, retry_after: Option<&str>) -> Result<Response, ReqwestError> {
+        let mut builder = http::Response::builder().status(status);
+        if let Some(retry_after) = retry_after {
+            builder = builder.header(RETRY_AFTER, retry_after);
+        }
+        Ok(Response::from(builder.body("").unwrap()))
+    }
+
+    /// Local upstream answering every request with `status` and counting them
+    async fn upstream(status: http::StatusCode, retry_after: &'static str) -> (String, Arc<AtomicU32>) {
+        let hits = Arc::new(AtomicU32::new(0));
+        let counter = hits.clone();
+        let app = Router::new().route(
+            "/",
+            get(move || {
+                counter.fetch_add(1, Ordering::SeqCst);
+                async move { (status, [(http::header::RETRY_AFTER, retry_after)], "[]") }
+            }),
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        (url, hits)
+    }
+
+    #[test]
+    fn server_errors_and_rate_limits_are_retried() {
+        let policy = policy(RetryConfig::default());
+
+        assert_eq!(policy.classify(&response(503, None), 1).map(|(reason, _)| reason), Some("server_error"));
+        assert_eq!(policy.classify(&response(429, None), 1).map(|(reason, _)| reason), Some("rate_limited"));
+        assert!(policy.classify(&response(404, None), 1).is_none());
+        assert!(policy.classify(&response(200, None), 1).is_none());
+    }
+
+    #[test]
+    fn retry_after_is_honoured_up_to_its_limit() {
+        let policy = policy(RetryConfig { max_retry_after_secs: 5, ..Default::default() });
+        let delay = |retry_after| policy.classify(&response(503, Some(retry_after)), 1).map(|(_, delay)| delay);
+
+        assert!(delay("2").unwrap() >= Duration::from_secs(2));
+        assert_eq!(delay("10"), None);
+        // A date in the past means now, so only the backoff is waited
+        assert!(delay("Wed, 21 Oct 2015 07:28:00 GMT").unwrap() <= Duration::from_millis(100));
+        assert!(delay("soon").unwrap() <= Duration::from_millis(100));
+    }
+
+    #[test]
+    fn backoff_is_jittered_under_an_exponential_ceiling() {
+        let policy = policy(RetryConfig { base_delay_ms: 100, max_delay_ms: 1000, ..Default::default() });
+
+        for _ in 0..100 {
+            assert!(policy.backoff(1) <= Duration::from_millis(100));
+            assert!(policy.backoff(3) <= Duration::from_millis(400));
+            assert!(policy.backoff(30) <= Duration::from_millis(1000));
+        }
+        let samples: Vec<_> = (0..100).map(|_| policy.backoff(3)).collect();
+        assert!(samples.iter().any(|delay| *delay != samples[0]), "backoff is not jittered");
+    }
+
+    #[test]
+    fn budget_starts_full_and_refills_by_the_ratio() {
+        let policy = policy(RetryConfig { budget_ratio: 0.5, budget_burst: 2, ..Default::default() });
+
+        assert!(policy.withdraw());
+        assert!(policy.withdraw());
+        assert!(!policy.withdraw());
+
+        policy.deposit();
+        assert!(!policy.withdraw());
+        policy.deposit();
+        assert!(policy.withdraw());
+
+        // Deposits never bank more than the burst
+        (0..10).for_each(|_| policy.deposit());
+        assert!(policy.withdraw() && policy.withdraw() && !policy.withdraw());
+    }
+
+    #[test]
+    fn disabled_retries_make_a_single_attempt() {
+        assert_eq!(policy(RetryConfig { enabled: false, ..Default::default() }).max_attempts(), 1);
+        assert_eq!(policy(RetryConfig { max_attempts: 4, ..Default::default() }).max_attempts(), 4);
+    }
+
+    #[tokio::test]
+    async fn connect_errors_are_retried() {
+        // Nothing listens on port 1
+        let result = Client::new().get("http://127.0.0.1:1/").send().await;
+
+        assert_eq!(policy(RetryConfig::default()).classify(&result, 1).map(|(reason, _)| reason), Some("connect"));
+    }
+
+    #[tokio::test]
+    async fn a_final_429_fails_instead_of_being_cached() {
+        let (url, hits) = upstream(http::StatusCode::TOO_MANY_REQUESTS, "0").await;
+        let policy = policy(RetryConfig { max_attempts: 3, base_delay_ms: 1, max_delay_ms: 1, ..Default::default() });
+
+        let response = Client::new().get(&url).send_with_retry(&policy).await.unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+
+        let fetched = response.json_cached::<Vec<u32>>().await;
+        assert!(matches!(fetched, Err(e) if e.status() == Some(StatusCode::TOO_MANY_REQUESTS)));
+    }
+
+    #[tokio::test]
+    async fn a_final_408_fails_instead_of_being_cached() {
+        let (url, _) = upstream(http::StatusCode::REQUEST_TIMEOUT, "0").await;
+        let policy = policy(RetryConfig { enabled: false, ..Default::default() });
+
+        let response = Client::new().get(&url).send_with_retry(&policy).await.unwrap();
+        let fetched = response.json_cached::<Vec<u32>>().await;
+
+        assert!(matches!(fetched, Err(e) if e.status() == Some(StatusCode::REQUEST_TIMEOUT)));
+    }
+
+    #[tokio::test]
+    async fn retries_stop_at_the_deadline() {
+        let (url, hits) = upstream(http::StatusCode::SERVICE_UNAVAILABLE, "2").await;
+        let policy = RetryPolicy::new(&HttpClientConfig { timeout_secs: 1, ..Default::default() });
+
+        let response = Client::new().get(&url).send_with_retry(&policy).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn successful_responses_are_not_retried() {
+        let (url, hits) = upstream(http::StatusCode::OK, "0").await;
+
+        let response = Client::new().get(&url).send_with_retry(&policy(RetryConfig::default())).await.unwrap();
+
+        assert!(matches!(response.json_cached::<Vec<u32>>().await, Ok(Fetched::Found { .. })));
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+    }
+}
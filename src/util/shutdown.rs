@@ -0,0 +1,102 @@
+use std::{future::Future, sync::Arc, time::Duration};
+
+use tokio::{signal, sync::watch};
+use tokio_util::{sync::CancellationToken, task::TaskTracker};
+use tracing::info;
+
+/// Shared shutdown state, flipped once SIGTERM/SIGINT is received.
+///
+/// Background tasks are spawned through it, so they can be stopped and awaited
+/// once the server is done instead of being killed when `main` returns.
+#[derive(Clone)]
+pub struct Shutdown {
+    draining: Arc<watch::Sender<bool>>,
+    tasks: TaskTracker,         // Every background task, awaited in `stop_tasks`
+    stopping: CancellationToken, // Cancelled once background loops should exit
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        let (draining, _) = watch::channel(false);
+        Self {
+            draining: Arc::new(draining),
+            tasks: TaskTracker::new(),
+            stopping: CancellationToken::new(),
+        }
+    }
+
+    /// Whether the server is draining and should be reported as not ready
+    pub fn is_draining(&self) -> bool {
+        *self.draining.borrow()
+    }
+
+    /// Marks the server as draining and wakes everyone waiting in `draining()`
+    pub fn begin_drain(&self) {
+        self.draining.send_replace(true);
+    }
+
+    /// Resolves once draining has started, immediately if it already has
+    pub async fn draining(&self) {
+        let mut receiver = self.draining.subscribe();
+        let _ = receiver.wait_for(|draining| *draining).await;
+    }
+
+    /// Tracker of background tasks; tasks spawned on it run to completion before shutdown
+    pub fn tasks(&self) -> &TaskTracker {
+        &self.tasks
+    }
+
+    /// Spawns a tracked background task
+    pub fn spawn<F>(&self, task: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        self.tasks.spawn(task);
+    }
+
+    /// Resolves once background loops should exit; they select on it where they wait
+    pub async fn stopping(&self) {
+        self.stopping.cancelled().await
+    }
+
+    /// Stops background loops and waits up to `timeout` for every tracked task,
+    /// returning whether they all finished
+    pub async fn stop_tasks(&self, timeout: Duration) -> bool {
+        self.stopping.cancel();
+        self.tasks.close();
+        tokio::time::timeout(timeout, self.tasks.wait()).await.is_ok()
+    }
+
+    /// Waits for SIGTERM or SIGINT and then starts draining
+    pub async fn listen_for_signals(self) {
+        let ctrl_c = async {
+            signal::ctrl_c()
+                .await
+                .expect("Failed to install SIGINT handler");
+        };
+
+        #[cfg(unix)]
+        let terminate = async {
+            signal::unix::signal(signal::unix::SignalKind::terminate())
+                .expect("Failed to install SIGTERM handler")
+                .recv()
+                .await;
+        };
+
+        #[cfg(not(unix))]
+        let terminate = std::future::pending::<()>();
+
+        tokio::select! {
+            _ = ctrl_c => info!("Received SIGINT, shutting down"),
+            _ = terminate => info!("Received SIGTERM, shutting down"),
+        }
+
+        self.begin_drain();
+    }
+}
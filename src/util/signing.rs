@@ -0,0 +1,113 @@
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Builds the string clients sign:
+///
+/// ```text
+/// METHOD\nPATH\nQUERY\nTIMESTAMP\nhex(sha256(BODY))[\nNONCE]
+/// ```
+///
+/// The nonce line is only present when the request carries `x-nonce`.
+pub fn canonical_request(
+    method: &str,
+    path: &str,
+    query: &str,
+    timestamp: &str,
+    body: &[u8],
+    nonce: Option<&str>,
+) -> String {
+    let body_hash = hex::encode(Sha256::digest(body));
+    let mut message = format!("{}\n{}\n{}\n{}\n{}", method, path, query, timestamp, body_hash);
+    if let Some(nonce) = nonce {
+        message.push('\n');
+        message.push_str(nonce);
+    }
+    message
+}
+
+/// Nonces are 16 to 128 characters of `[A-Za-z0-9_-]`
+pub fn is_valid_nonce(nonce: &str) -> bool {
+    (16..=128).contains(&nonce.len())
+        && nonce.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+}
+
+/// Whether `candidate` is one of the configured `secrets`, such as API keys or
+/// admin tokens. Digests are compared rather than the values, so the time taken
+/// doesn't reveal how much of a secret a guess got right.
+pub fn matches_secret(candidate: &str, secrets: &[String]) -> bool {
+    let digest = Sha256::digest(candidate.as_bytes());
+    secrets.iter().any(|secret| Sha256::digest(secret.as_bytes()) == digest)
+}
+
+/// Verifies a hex-encoded signature in constant time
+pub fn verify(secret: &str, message: &str, signature: &str) -> bool {
+    let Ok(signature) = hex::decode(signature) else {
+        return false;
+    };
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(message.as_bytes());
+    mac.verify_slice(&signature).is_ok()
+}
+
+/// Hex-encoded signature of `message`, as clients compute it
+#[cfg(test)]
+pub fn sign(secret: &str, message: &str) -> String {
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(message.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "0123456789abcdef0123456789abcdef";
+
+    #[test]
+    fn signatures_round_trip() {
+        let message = canonical_request("POST", "/v1/users", "page=2", "1700000000", b"{}", Some("nonce-0123456789ab"));
+        let signature = sign(SECRET, &message);
+
+        assert!(verify(SECRET, &message, &signature));
+        assert!(verify(SECRET, &message, &signature.to_uppercase()));
+        assert!(!verify("another secret of at least 32 bytes", &message, &signature));
+        assert!(!verify(SECRET, &message.replace("page=2", "page=3"), &signature));
+        assert!(!verify(SECRET, &message, "not hex"));
+    }
+
+    #[test]
+    fn canonical_request_has_one_line_per_part() {
+        let body_hash = hex::encode(Sha256::digest(b"body"));
+
+        assert_eq!(
+            canonical_request("GET", "/v1/user/1", "", "1700000000", b"body", None),
+            format!("GET\n/v1/user/1\n\n1700000000\n{}", body_hash)
+        );
+        assert_eq!(
+            canonical_request("GET", "/v1/user/1", "", "1700000000", b"body", Some("n")),
+            format!("GET\n/v1/user/1\n\n1700000000\n{}\nn", body_hash)
+        );
+    }
+
+    #[test]
+    fn secrets_match_exactly() {
+        let secrets = vec![SECRET.to_string(), "another secret of at least 32 bytes".to_string()];
+
+        assert!(matches_secret(SECRET, &secrets));
+        assert!(!matches_secret(&SECRET[1..], &secrets));
+        assert!(!matches_secret(&SECRET.to_uppercase(), &secrets));
+        assert!(!matches_secret(SECRET, &[]));
+    }
+
+    #[test]
+    fn nonces_are_bounded_and_url_safe() {
+        assert!(is_valid_nonce("0123456789abcdef"));
+        assert!(is_valid_nonce(&"a-_".repeat(42)));
+        assert!(!is_valid_nonce("too-short"));
+        assert!(!is_valid_nonce(&"a".repeat(129)));
+        assert!(!is_valid_nonce("0123456789abcdef/"));
+    }
+}
@@ -0,0 +1,187 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Arc, Mutex},
+};
+
+use tokio::sync::watch;
+
+type Calls<V> = Arc<Mutex<HashMap<String, watch::Receiver<Option<V>>>>>;
+
+/// Coalesces concurrent calls for the same key into one execution.
+///
+/// The first caller for a key runs the work; callers arriving while it is in
+/// flight wait for and receive a clone of its result. If the leading call is
+/// cancelled, one of the waiters takes over.
+pub struct SingleFlight<V> {
+    calls: Calls<V>,
+}
+
+impl<V> Clone for SingleFlight<V> {
+    fn clone(&self) -> Self {
+        Self {
+            calls: self.calls.clone(),
+        }
+    }
+}
+
+impl<V> Default for SingleFlight<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+enum Role<V> {
+    Leader(watch::Sender<Option<V>>),
+    Waiter(watch::Receiver<Option<V>>),
+}
+
+/// Removes the in-flight entry when the leader finishes or is dropped
+struct LeaderGuard<V> {
+    calls: Calls<V>,
+    key: String,
+}
+
+impl<V> Drop for LeaderGuard<V> {
+    fn drop(&mut self) {
+        self.calls.lock().unwrap().remove(&self.key);
+    }
+}
+
+impl<V> SingleFlight<V> {
+    pub fn new() -> Self {
+        Self {
+            calls: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Number of keys currently being worked on
+    pub fn in_flight(&self) -> usize {
+        self.calls.lock().unwrap().len()
+    }
+}
+
+impl<V: Clone> SingleFlight<V> {
+    /// Runs `work` unless a call for `key` is already in flight, in which case
+    /// its result is awaited instead. Returns the result and whether it was shared.
+    pub async fn run<F, Fut>(&self, key: &str, work: F) -> (V, bool)
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = V>,
+    {
+        let mut work = Some(work);
+
+        loop {
+            let role = {
+                let mut calls = self.calls.lock().unwrap();
+                match calls.get(key) {
+                    Some(receiver) => Role::Waiter(receiver.clone()),
+                    None => {
+                        let (sender, receiver) = watch::channel(None);
+                        calls.insert(key.to_string(), receiver);
+                        Role::Leader(sender)
+                    }
+                }
+            };
+
+            match role {
+                Role::Waiter(mut receiver) => {
+                    if let Ok(value) = receiver.wait_for(Option::is_some).await {
+                        return (value.clone().expect("checked by wait_for"), true);
+                    }
+                    // The leader was dropped before finishing, try to take over
+                }
+                Role::Leader(sender) => {
+                    let _guard = LeaderGuard {
+                        calls: self.calls.clone(),
+                        key: key.to_string(),
+                    };
+                    let work = work.take().expect("work only runs once");
+                    let value = work().await;
+                    sender.send_replace(Some(value.clone()));
+                    return (value, false);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    use futures_util::{future::join_all, poll};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn concurrent_calls_share_one_execution() {
+        let flights = SingleFlight::new();
+        let calls = AtomicUsize::new(0);
+
+        let results = join_all((0..10).map(|_| {
+            flights.run("key", || async {
+                calls.fetch_add(1, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                42
+            })
+        }))
+        .await;
+
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert!(results.iter().all(|(value, _)| *value == 42));
+        assert_eq!(results.iter().filter(|(_, shared)| *shared).count(), 9);
+        assert_eq!(flights.in_flight(), 0);
+    }
+
+    #[tokio::test]
+    async fn different_keys_run_separately() {
+        let flights = SingleFlight::new();
+
+        let (a, b) = tokio::join!(flights.run("a", || async { 1 }), flights.run("b", || async { 2 }));
+
+        assert_eq!((a, b), ((1, false), (2, false)));
+    }
+
+    #[tokio::test]
+    async fn a_waiter_takes_over_when_the_leader_is_dropped() {
+        let flights = SingleFlight::new();
+        let mut leader = Box::pin(flights.run("key", std::future::pending::<u32>));
+        let mut waiter = Box::pin(flights.run("key", || async { 7 }));
+        assert!(poll!(&mut leader).is_pending());
+        assert!(poll!(&mut waiter).is_pending());
+
+        drop(leader);
+
+        assert_eq!(waiter.await, (7, false));
+        assert_eq!(flights.in_flight(), 0);
+    }
+
+    #[tokio::test]
+    async fn a_waiter_takes_over_when_the_leader_panics() {
+        let flights = SingleFlight::<u32>::new();
+        let leader = tokio::spawn({
+            let flights = flights.clone();
+            async move {
+                flights
+                    .run("key", || async {
+                        tokio::time::sleep(Duration::from_millis(20)).await;
+                        panic!("upstream fetch panicked");
+                    })
+                    .await
+            }
+        });
+        while flights.in_flight() == 0 {
+            tokio::task::yield_now().await;
+        }
+
+        let result = flights.run("key", || async { 7 }).await;
+
+        assert!(leader.await.unwrap_err().is_panic());
+        assert_eq!(result, (7, false));
+        assert_eq!(flights.in_flight(), 0);
+    }
+}